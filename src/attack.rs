@@ -1,3 +1,5 @@
+use crate::replay_response::ClearType;
+
 const ATTACK_TABLE: [[[usize; 21]; 9]; 5] = [
     // straight taken from osk's table: https://cdn.discordapp.com/attachments/674421736162197515/716081165886423110/2020-05-30_02-07-18.png
     [
@@ -164,3 +166,19 @@ fn btb_level(btb: usize) -> usize {
     }
     8// next "level" starts at ~3725 but we're keeping it oskreveal
 }
+
+///row of the attack table for a clear, none when it doesn't clear lines
+pub fn attack_index(clear_type: ClearType) -> Option<usize> {
+    match clear_type {
+        ClearType::Single => Some(0),
+        ClearType::Double => Some(1),
+        ClearType::Triple => Some(2),
+        ClearType::Quad | ClearType::Penta => Some(3),
+        ClearType::TspinMiniSingle => Some(4),
+        ClearType::TspinSingle => Some(5),
+        ClearType::TspinMiniDouble => Some(6),
+        ClearType::TspinDouble => Some(7),
+        ClearType::TspinTriple | ClearType::TspinQuad | ClearType::TspinPenta => Some(8),
+        _ => None,
+    }
+}
@@ -0,0 +1,184 @@
+use evaluator::binary::{binary_to_json, is_binary, json_to_binary, read_games};
+use evaluator::envelope::{input_schema, output_schema, Envelope};
+use evaluator::export::{placement_rows, write_csv};
+use evaluator::key_moments::{key_moments_games, DEFAULT_KEY_MOMENTS};
+use evaluator::position::{analyze_position, parse_board, parse_queue};
+use evaluator::review::review_games;
+use evaluator::stats_diff::{diff, read_player_stats};
+use evaluator::{analyze_games, GameInput, MinoType};
+use std::process::ExitCode;
+
+const USAGE: &str = "usage:
+  evaluate analyze <games file>... [--min-samples N]
+  evaluate convert <input> <output>
+  evaluate diff <before stats> <after stats>
+  evaluate export <games file>... [--output FILE]
+  evaluate moments <games file>... [--top N]
+  evaluate review <games file>...
+  evaluate schema <input|output>
+  evaluate position <board json or fumen> [--width N] [--hold PIECE] [--queue PIECES] [--btb N] [--combo N]";
+
+///value of every `--name value` pair after the positional arguments
+fn option<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|arg| arg == name)
+        .and_then(|i| args.get(i + 1))
+        .map(String::as_str)
+}
+
+fn number(args: &[String], name: &str, default: usize) -> Result<usize, String> {
+    option(args, name).map_or(Ok(default), |value| {
+        value
+            .parse()
+            .map_err(|_| format!("{} needs a number, got {}", name, value))
+    })
+}
+
+fn position(args: &[String]) -> Result<String, String> {
+    let board = args.first().ok_or("position needs a board")?;
+    let width = number(args, "--width", 10)?;
+    let (board, width) = parse_board(board, width).map_err(|error| error.to_string())?;
+    let hold = parse_queue(option(args, "--hold").unwrap_or_default())
+        .map_err(|error| error.to_string())?;
+    let queue = parse_queue(option(args, "--queue").unwrap_or_default())
+        .map_err(|error| error.to_string())?;
+    let analysis = analyze_position(
+        &board,
+        width,
+        hold.first().copied().unwrap_or(MinoType::Empty),
+        &queue,
+        number(args, "--btb", 0)?,
+        number(args, "--combo", 0)?,
+    );
+    Ok(serde_json::to_string_pretty(&analysis).unwrap())
+}
+
+fn read(path: &str) -> Result<Vec<u8>, String> {
+    std::fs::read(path).map_err(|error| format!("{}: {}", path, error))
+}
+
+///games from every file that isn't an option value, files are json or binary, whichever they start like
+fn read_all_games(args: &[String]) -> Result<Vec<GameInput>, String> {
+    let mut games = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg.starts_with("--") {
+            args.next();
+            continue;
+        }
+        let bytes = read(arg)?;
+        games.extend(read_games(&bytes).map_err(|error| format!("{}: {}", arg, error))?);
+    }
+    if games.is_empty() {
+        return Err("needs at least one game".to_string());
+    }
+    Ok(games)
+}
+
+fn analyze(args: &[String]) -> Result<String, String> {
+    let min_samples = number(args, "--min-samples", 0)?;
+    let games = read_all_games(args)?;
+    let stats = Envelope::new(analyze_games(&games, min_samples));
+    Ok(serde_json::to_string_pretty(&stats).unwrap())
+}
+
+///the biggest spikes, hesitations, missed attacks and defence collapses of every game
+fn moments(args: &[String]) -> Result<String, String> {
+    let count = number(args, "--top", DEFAULT_KEY_MOMENTS)?;
+    let moments = Envelope::new(key_moments_games(&read_all_games(args)?, count));
+    Ok(serde_json::to_string_pretty(&moments).unwrap())
+}
+
+///every placement with its tags, for a move by move review
+fn review(args: &[String]) -> Result<String, String> {
+    let reviews = Envelope::new(review_games(&read_all_games(args)?));
+    Ok(serde_json::to_string_pretty(&reviews).unwrap())
+}
+
+///a row per placement as csv, or as parquet when the output ends in `.parquet`
+fn export(args: &[String]) -> Result<String, String> {
+    let rows = placement_rows(&read_all_games(args)?);
+    let Some(output) = option(args, "--output") else {
+        let mut csv = Vec::new();
+        write_csv(&rows, &mut csv).map_err(|error| error.to_string())?;
+        return Ok(String::from_utf8_lossy(&csv).trim_end().to_string());
+    };
+    let file = std::fs::File::create(output).map_err(|error| format!("{}: {}", output, error))?;
+    if output.ends_with(".parquet") {
+        #[cfg(feature = "parquet")]
+        evaluator::export::write_parquet(&rows, file).map_err(|error| error.to_string())?;
+        #[cfg(not(feature = "parquet"))]
+        return Err("parquet output needs the parquet feature".to_string());
+    } else {
+        write_csv(&rows, file).map_err(|error| error.to_string())?;
+    }
+    Ok(format!("wrote {} placements to {}", rows.len(), output))
+}
+
+///turns json into binary and binary into json
+fn convert(args: &[String]) -> Result<String, String> {
+    let [input, output] = args else {
+        return Err("convert needs an input and an output".to_string());
+    };
+    let bytes = read(input)?;
+    let converted = if is_binary(&bytes) {
+        binary_to_json(&bytes).map(String::into_bytes)
+    } else {
+        json_to_binary(&String::from_utf8_lossy(&bytes))
+    }
+    .map_err(|error| format!("{}: {}", input, error))?;
+    std::fs::write(output, &converted).map_err(|error| format!("{}: {}", output, error))?;
+    Ok(format!(
+        "wrote {} bytes from {} bytes to {}",
+        converted.len(),
+        bytes.len(),
+        output
+    ))
+}
+
+///compares two outputs of `analyze`
+fn diff_stats(args: &[String]) -> Result<String, String> {
+    let [before, after] = args else {
+        return Err("diff needs the stats before and after".to_string());
+    };
+    let [before, after] = [before, after].map(|path| {
+        let json = String::from_utf8_lossy(&read(path)?).into_owned();
+        read_player_stats(&json).map_err(|error| format!("{}: {}", path, error))
+    });
+    let diff = Envelope::new(diff(&before?, &after?));
+    Ok(serde_json::to_string_pretty(&diff).unwrap())
+}
+
+fn schema(args: &[String]) -> Result<String, String> {
+    let schema = match args.first().map(String::as_str) {
+        Some("input") => input_schema(),
+        Some("output") => output_schema(),
+        _ => return Err("schema needs input or output".to_string()),
+    };
+    Ok(serde_json::to_string_pretty(&schema).unwrap())
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("analyze") => analyze(&args[1..]),
+        Some("convert") => convert(&args[1..]),
+        Some("diff") => diff_stats(&args[1..]),
+        Some("export") => export(&args[1..]),
+        Some("moments") => moments(&args[1..]),
+        Some("position") => position(&args[1..]),
+        Some("review") => review(&args[1..]),
+        Some("schema") => schema(&args[1..]),
+        _ => Err(USAGE.to_string()),
+    };
+    match result {
+        Ok(output) => {
+            println!("{}", output);
+            ExitCode::SUCCESS
+        }
+        Err(error) => {
+            eprintln!("{}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,438 @@
+use crate::envelope::read_versioned;
+use crate::match_report::{Game, GameInput, GameMeta};
+use crate::replay_response::{ClearType, MinoType, PlacementStats, MAX_BOARD_WIDTH};
+
+///start of every binary file, the last byte is the format version
+pub const MAGIC: &[u8; 4] = b"EVB\x01";
+
+//bits of the byte that says which optional metadata fields follow
+const MATCH_ID: u8 = 1;
+const ROUND: u8 = 1 << 1;
+const OPPONENT_ID: u8 = 1 << 2;
+const MODE: u8 = 1 << 3;
+const WON: u8 = 1 << 4;
+const START_TIME: u8 = 1 << 5;
+
+///tallest board a file can ask for, far above any real game so only a corrupt header gets near it
+const MAX_BOARD_HEIGHT: usize = 1024;
+
+#[derive(Debug)]
+pub struct BinaryError(pub String);
+impl std::fmt::Display for BinaryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("Invalid games: {}", self.0))?;
+        Ok(())
+    }
+}
+impl std::error::Error for BinaryError {}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn write_list(out: &mut Vec<u8>, values: &[usize]) {
+    write_varint(out, values.len());
+    for &value in values {
+        write_varint(out, value);
+    }
+}
+
+fn write_string(out: &mut Vec<u8>, value: &str) {
+    write_varint(out, value.len());
+    out.extend_from_slice(value.as_bytes());
+}
+
+///cells that differ from the previous board as the gap since the last change and the new piece
+fn write_board_delta(out: &mut Vec<u8>, previous: Option<&[MinoType]>, board: &[MinoType]) {
+    let changes: Vec<_> = board
+        .iter()
+        .enumerate()
+        .filter(|&(i, &mino)| previous.map_or(MinoType::Empty, |previous| previous[i]) != mino)
+        .collect();
+    write_varint(out, changes.len());
+    let mut last = 0;
+    for (i, &mino) in changes {
+        write_varint(out, i - last);
+        out.push(mino as u8);
+        last = i;
+    }
+}
+
+fn write_placement(
+    out: &mut Vec<u8>,
+    previous: Option<&PlacementStats>,
+    placement: &PlacementStats,
+) {
+    out.push(placement.shape as u8);
+    write_varint(out, placement.lines_cleared);
+    write_varint(out, placement.garbage_cleared);
+    write_varint(out, placement.keypresses);
+    write_list(out, &placement.attack);
+    out.push(placement.clear_type as u8);
+    write_varint(out, placement.combo);
+    write_varint(out, placement.btb_chain);
+    out.push(placement.btb_clear as u8);
+    out.extend_from_slice(&placement.frame_delay.to_le_bytes());
+    write_list(out, &placement.attack_received);
+    write_list(out, &placement.attack_tanked);
+    write_varint(out, placement.queue.len());
+    out.extend(placement.queue.iter().map(|&mino| mino as u8));
+    write_varint(out, placement.board_width);
+    write_varint(out, placement.board_height);
+    //boards only change by a piece and some garbage, a board of another size starts over from empty
+    let previous = previous
+        .filter(|previous| previous.board.len() == placement.board.len())
+        .map(|previous| previous.board.as_slice());
+    write_board_delta(out, previous, &placement.board);
+}
+
+fn write_meta(out: &mut Vec<u8>, meta: &GameMeta) {
+    write_string(out, &meta.game_id);
+    let fields = [
+        (MATCH_ID, meta.match_id.is_some()),
+        (ROUND, meta.round.is_some()),
+        (OPPONENT_ID, meta.opponent_id.is_some()),
+        (MODE, meta.mode.is_some()),
+        (WON, meta.won.is_some()),
+        (START_TIME, meta.start_time.is_some()),
+    ];
+    out.push(
+        fields
+            .iter()
+            .filter(|(_, present)| *present)
+            .fold(0, |flags, (flag, _)| flags | flag),
+    );
+    for value in [
+        &meta.match_id,
+        &meta.opponent_id,
+        &meta.mode,
+        &meta.start_time,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        write_string(out, value);
+    }
+    if let Some(round) = meta.round {
+        write_varint(out, round);
+    }
+    if let Some(won) = meta.won {
+        out.push(won as u8);
+    }
+}
+
+///packs games into the binary format, every game is prefixed with its length so readers can skip it
+pub fn encode_games(games: &[GameInput]) -> Vec<u8> {
+    let mut out = MAGIC.to_vec();
+    write_varint(&mut out, games.len());
+    for game in games {
+        let mut body = Vec::new();
+        match game.meta() {
+            Some(meta) => {
+                body.push(1);
+                write_meta(&mut body, meta);
+            }
+            None => body.push(0),
+        }
+        let placements = game.placements();
+        write_varint(&mut body, placements.len());
+        let mut previous = None;
+        for placement in placements {
+            write_placement(&mut body, previous, placement);
+            previous = Some(placement);
+        }
+        write_varint(&mut out, body.len());
+        out.extend(body);
+    }
+    out
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], BinaryError> {
+        if len > self.bytes.len() {
+            return Err(BinaryError("data ended early".to_string()));
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn byte(&mut self) -> Result<u8, BinaryError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn varint(&mut self) -> Result<usize, BinaryError> {
+        let mut value = 0;
+        for shift in (0..usize::BITS).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as usize) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(BinaryError("varint too long".to_string()))
+    }
+
+    fn list(&mut self) -> Result<Vec<usize>, BinaryError> {
+        let len = self.varint()?;
+        (0..len).map(|_| self.varint()).collect()
+    }
+
+    fn string(&mut self) -> Result<String, BinaryError> {
+        let len = self.varint()?;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|error| BinaryError(error.to_string()))
+    }
+
+    fn mino(&mut self) -> Result<MinoType, BinaryError> {
+        MinoType::try_from(self.byte()?).map_err(|error| BinaryError(error.to_string()))
+    }
+
+    fn placement(
+        &mut self,
+        previous: Option<&PlacementStats>,
+    ) -> Result<PlacementStats, BinaryError> {
+        let shape = self.mino()?;
+        let lines_cleared = self.varint()?;
+        let garbage_cleared = self.varint()?;
+        let keypresses = self.varint()?;
+        let attack = self.list()?;
+        let clear_type =
+            ClearType::try_from(self.byte()?).map_err(|error| BinaryError(error.to_string()))?;
+        let combo = self.varint()?;
+        let btb_chain = self.varint()?;
+        let btb_clear = self.byte()? != 0;
+        let frame_delay = f64::from_le_bytes(self.take(8)?.try_into().unwrap()); //took exactly 8 bytes
+        let attack_received = self.list()?;
+        let attack_tanked = self.list()?;
+        let queue_len = self.varint()?;
+        let queue = (0..queue_len)
+            .map(|_| self.mino())
+            .collect::<Result<_, _>>()?;
+        let board_width = self.varint()?;
+        let board_height = self.varint()?;
+
+        //checked before the board is allocated, validating the placement afterwards would be too late
+        if board_width > MAX_BOARD_WIDTH || board_height > MAX_BOARD_HEIGHT {
+            return Err(BinaryError(format!(
+                "board of {}x{} is too big",
+                board_width, board_height
+            )));
+        }
+        let cells = board_width * board_height;
+        let mut board = match previous {
+            Some(previous) if previous.board.len() == cells => previous.board.clone(),
+            _ => vec![MinoType::Empty; cells],
+        };
+        let changes = self.varint()?;
+        let mut i: usize = 0;
+        for _ in 0..changes {
+            i = i
+                .checked_add(self.varint()?)
+                .ok_or_else(|| BinaryError("board change out of bounds".to_string()))?;
+            let mino = self.mino()?;
+            *board
+                .get_mut(i)
+                .ok_or_else(|| BinaryError("board change out of bounds".to_string()))? = mino;
+        }
+
+        let placement = PlacementStats {
+            shape,
+            lines_cleared,
+            garbage_cleared,
+            keypresses,
+            attack,
+            clear_type,
+            combo,
+            btb_chain,
+            btb_clear,
+            frame_delay,
+            attack_received,
+            attack_tanked,
+            board,
+            queue,
+            board_width,
+            board_height,
+        };
+        placement
+            .validate()
+            .map_err(|error| BinaryError(error.to_string()))?;
+        Ok(placement)
+    }
+
+    fn meta(&mut self) -> Result<GameMeta, BinaryError> {
+        let game_id = self.string()?;
+        let flags = self.byte()?;
+        let mut string = |flag: u8| -> Result<Option<String>, BinaryError> {
+            (flags & flag != 0).then(|| self.string()).transpose()
+        };
+        let match_id = string(MATCH_ID)?;
+        let opponent_id = string(OPPONENT_ID)?;
+        let mode = string(MODE)?;
+        let start_time = string(START_TIME)?;
+        let round = (flags & ROUND != 0).then(|| self.varint()).transpose()?;
+        let won = (flags & WON != 0)
+            .then(|| self.byte().map(|won| won != 0))
+            .transpose()?;
+        Ok(GameMeta {
+            game_id,
+            match_id,
+            round,
+            opponent_id,
+            mode,
+            won,
+            start_time,
+        })
+    }
+
+    fn game(&mut self) -> Result<GameInput, BinaryError> {
+        let meta = match self.byte()? {
+            0 => None,
+            1 => Some(self.meta()?),
+            kind => return Err(BinaryError(format!("unknown game kind {}", kind))),
+        };
+        let len = self.varint()?;
+        let mut placements: Vec<PlacementStats> = Vec::new();
+        for _ in 0..len {
+            let placement = self.placement(placements.last())?;
+            placements.push(placement);
+        }
+        Ok(match meta {
+            Some(meta) => GameInput::Game(Game { meta, placements }),
+            None => GameInput::Placements(placements),
+        })
+    }
+}
+
+///true if the bytes start like the binary format rather than json
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+pub fn decode_games(bytes: &[u8]) -> Result<Vec<GameInput>, BinaryError> {
+    if !is_binary(bytes) {
+        return Err(BinaryError("missing header".to_string()));
+    }
+    let mut reader = Reader {
+        bytes: &bytes[MAGIC.len()..],
+    };
+    let count = reader.varint()?;
+    let mut games = Vec::new();
+    for _ in 0..count {
+        let len = reader.varint()?;
+        let mut game = Reader {
+            bytes: reader.take(len)?,
+        };
+        games.push(game.game()?);
+    }
+    Ok(games)
+}
+
+fn parse_json(json: &str) -> Result<Vec<GameInput>, BinaryError> {
+    match read_versioned::<Vec<GameInput>>(json) {
+        Ok(games) => Ok(games),
+        Err(_) => read_versioned::<GameInput>(json)
+            .map(|game| vec![game])
+            .map_err(|error| BinaryError(error.to_string())),
+    }
+}
+
+///games in either format, json can be an array of games or a single game with or without an envelope
+pub fn read_games(bytes: &[u8]) -> Result<Vec<GameInput>, BinaryError> {
+    if is_binary(bytes) {
+        return decode_games(bytes);
+    }
+    let json = std::str::from_utf8(bytes).map_err(|error| BinaryError(error.to_string()))?;
+    let games = parse_json(json)?;
+    for placement in games.iter().flat_map(GameInput::placements) {
+        placement
+            .validate()
+            .map_err(|error| BinaryError(error.to_string()))?;
+    }
+    Ok(games)
+}
+
+///packs a json array of games, or a single game, into the binary format
+pub fn json_to_binary(json: &str) -> Result<Vec<u8>, BinaryError> {
+    Ok(encode_games(&read_games(json.as_bytes())?))
+}
+
+///writes binary games back out as a json array of games
+pub fn binary_to_json(bytes: &[u8]) -> Result<String, BinaryError> {
+    let games = decode_games(bytes)?;
+    Ok(serde_json::to_string(&games).unwrap()) //games always serialize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn placement(board: Value, width: usize, height: usize) -> Value {
+        json!({
+            "shape": 6,
+            "linesCleared": 1,
+            "downstackCleared": 1,
+            "keypresses": 4,
+            "attack": [2],
+            "type": "SINGLE",
+            "combo": 1,
+            "BTBChain": 2,
+            "BTBClear": true,
+            "frameDelay": 12.5,
+            "attackRecieved": [3, 1],
+            "attackTanked": [1],
+            "board": board,
+            "queue": [0, 1, 2, 3],
+            "boardWidth": width,
+            "boardHeight": height,
+        })
+    }
+
+    #[test]
+    fn json_round_trips_through_binary() {
+        let empty = vec![8; 40];
+        let mut garbage = empty.clone();
+        garbage[30..39].fill(7);
+        let mut piece = garbage.clone();
+        piece[0] = 6;
+        let games = json!([
+            {
+                "gameId": "game",
+                "matchId": "match",
+                "round": 2,
+                "won": false,
+                "placements": [placement(json!(garbage), 10, 4), placement(json!(piece), 10, 4)],
+            },
+            //a board of another size in the same game starts over from empty
+            [placement(json!(piece), 10, 4), placement(json!(vec![8; 12]), 4, 3)],
+        ]);
+
+        let binary = json_to_binary(&games.to_string()).unwrap();
+        assert!(is_binary(&binary));
+        let json: Value = serde_json::from_str(&binary_to_json(&binary).unwrap()).unwrap();
+        let expected = serde_json::to_value(read_games(games.to_string().as_bytes()).unwrap());
+        assert_eq!(json, expected.unwrap());
+    }
+
+    #[test]
+    fn oversized_boards_are_refused_before_they_are_allocated() {
+        let games = json!([[placement(json!(vec![8; 40]), 10, 4)]]);
+        let mut binary = json_to_binary(&games.to_string()).unwrap();
+        //the board height is the byte before the board's changes, which is the last byte of an empty board
+        let height = binary.len() - 2;
+        assert_eq!(binary[height], 4);
+        binary.splice(height..=height, [0xff, 0xff, 0xff, 0xff, 0x0f]);
+        //the game's length prefix grows with it
+        binary[MAGIC.len() + 1] += 4;
+        let error = decode_games(&binary).unwrap_err();
+        assert!(error.0.contains("too big"), "{}", error);
+    }
+}
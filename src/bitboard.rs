@@ -0,0 +1,192 @@
+use crate::replay_response::{Board, MinoType};
+use bitris::prelude::{Board64, Location};
+
+///rows a bitris board can hold
+const BOARD64_HEIGHT: usize = 64;
+
+///packed board where every row is a bitmask of filled cells, bit `x` is column `x` and row 0 is the bottom
+///
+///colours are kept in their own layer so the masks stay cheap to scan
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BitBoard {
+    width: usize,
+    rows: Vec<u64>,
+    garbage: Vec<u64>,
+    colors: Vec<MinoType>,
+}
+
+impl BitBoard {
+    ///pack a replay response board, which is stored top row first
+    pub fn new(board: &Board, width: usize) -> Self {
+        let height = board.len() / width;
+        let mut rows = vec![0; height];
+        let mut garbage = vec![0; height];
+        let mut colors = vec![MinoType::Empty; width * height];
+        for y in 0..height {
+            for x in 0..width {
+                let mino = board[x + (height - 1 - y) * width];
+                if mino != MinoType::Empty {
+                    rows[y] |= 1 << x;
+                }
+                if mino == MinoType::Garbage {
+                    garbage[y] |= 1 << x;
+                }
+                colors[x + y * width] = mino;
+            }
+        }
+        Self {
+            width,
+            rows,
+            garbage,
+            colors,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.rows.len()
+    }
+
+    ///mask with a bit set for every column
+    pub fn full_row(&self) -> u64 {
+        u64::MAX >> (64 - self.width)
+    }
+
+    pub fn row(&self, y: usize) -> u64 {
+        self.rows[y]
+    }
+
+    pub fn garbage_row(&self, y: usize) -> u64 {
+        self.garbage[y]
+    }
+
+    pub fn is_filled(&self, x: usize, y: usize) -> bool {
+        self.rows[y] >> x & 1 == 1
+    }
+
+    #[allow(dead_code)]
+    pub fn color(&self, x: usize, y: usize) -> MinoType {
+        self.colors[x + y * self.width]
+    }
+
+    ///amount of rows up to the highest filled cell
+    pub fn stack_height(&self) -> usize {
+        self.rows
+            .iter()
+            .rposition(|&row| row != 0)
+            .map_or(0, |y| y + 1)
+    }
+
+    pub fn column_heights(&self) -> Vec<usize> {
+        let mut heights = vec![0; self.width];
+        let mut remaining = self.full_row();
+        for (y, &row) in self.rows.iter().enumerate().rev() {
+            let mut found = row & remaining;
+            remaining &= !row;
+            while found != 0 {
+                heights[found.trailing_zeros() as usize] = y + 1;
+                found &= found - 1;
+            }
+            if remaining == 0 {
+                break;
+            }
+        }
+        heights
+    }
+
+    ///convert into a bitris board, narrower boards are walled off on the right
+    ///
+    ///wider or taller boards can't be represented
+    pub fn to_board64(&self) -> Option<Board64> {
+        if self.width > 10 || self.height() > BOARD64_HEIGHT {
+            return None;
+        }
+        let walls = !self.full_row() & 0x3ff;
+        let mut board64 = Board64::blank();
+        for (y, &row) in self.rows.iter().enumerate() {
+            let mut cells = row | walls;
+            while cells != 0 {
+                board64.set_at(Location {
+                    x: cells.trailing_zeros() as i32,
+                    y: y as i32,
+                });
+                cells &= cells - 1;
+            }
+        }
+        Some(board64)
+    }
+
+    ///convert into a blockfish matrix with the bottom `skip_rows` rows left out
+    pub fn to_basic_matrix(&self, skip_rows: usize) -> blockfish::BasicMatrix {
+        let mut matrix = blockfish::BasicMatrix::with_cols(self.width as u16);
+        for (y, &row) in self.rows.iter().enumerate().skip(skip_rows) {
+            let mut cells = row;
+            while cells != 0 {
+                matrix.set(((y - skip_rows) as u16, cells.trailing_zeros() as u16));
+                cells &= cells - 1;
+            }
+        }
+        matrix
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{board, board_of_width};
+
+    ///the blockfish matrix as it was built from an unpacked board
+    fn unpacked_matrix(board: &Board, width: usize, skip_rows: usize) -> blockfish::BasicMatrix {
+        let board_height = board.len() / width;
+        let mut matrix = blockfish::BasicMatrix::with_cols(width as u16);
+        for y in 0..(board_height - skip_rows) {
+            for x in 0..width {
+                if board[x + y * width] != MinoType::Empty {
+                    matrix.set(((board_height - 1 - skip_rows - y) as u16, x as u16));
+                }
+            }
+        }
+        matrix
+    }
+
+    #[test]
+    fn basic_matrix_matches_the_unpacked_one() {
+        let boards = [
+            (
+                board(&["..T.......", ".TTT...LLL", "#####.####", "######.###"]),
+                10,
+            ),
+            (board_of_width(4, &["I...", "I.O.", "I#OO", "##.#"]), 4),
+            (
+                board_of_width(12, &["....SS......", "...SS.....J.", "###########."]),
+                12,
+            ),
+        ];
+        for (cells, width) in boards {
+            let packed = BitBoard::new(&cells, width);
+            for skip_rows in 0..3 {
+                //the matrix has no cell accessors, its debug output lists the cells
+                assert_eq!(
+                    format!("{:?}", packed.to_basic_matrix(skip_rows)),
+                    format!("{:?}", unpacked_matrix(&cells, width, skip_rows)),
+                    "skipping {} rows of {:?}",
+                    skip_rows,
+                    cells
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rows_are_packed_bottom_first() {
+        let packed = BitBoard::new(&board_of_width(4, &["I...", "I#OO"]), 4);
+        assert_eq!(packed.row(0), 0b1111);
+        assert_eq!(packed.garbage_row(0), 0b0010);
+        assert_eq!(packed.row(1), 0b0001);
+        assert_eq!(packed.column_heights(), vec![2, 1, 1, 1]);
+        assert_eq!(packed.color(1, 0), MinoType::Garbage);
+    }
+}
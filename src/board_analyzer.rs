@@ -1,63 +1,158 @@
-use crate::replay_response::{Board, MinoType};
+use crate::bitboard::BitBoard;
+use serde::{Deserialize, Serialize};
 
-pub fn get_height(board: &Board) -> usize {
-    for y in 0..40 {
-        for x in 0..10 {
-            if board[x + y * 10] != MinoType::Empty {
-                return 40 - y;
-            }
-        }
-    }
-    0
+///stack quality metrics of a single board
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct BoardFeatures {
+    pub column_heights: Vec<usize>,
+    pub bumpiness: usize,
+    pub holes: usize,
+    ///filled cells stacked above every hole
+    pub hole_depth: usize,
+    ///filled cells that have at least one hole below them
+    pub covered_cells: usize,
+    ///filled cells with an empty cell directly below
+    pub overhangs: usize,
+    pub row_transitions: usize,
+    pub column_transitions: usize,
+    pub wells: usize,
+    pub well_depth: usize,
+    pub t_slots: usize,
+    ///difference between filled cells on the two colours of a checkerboard
+    pub parity: usize,
+}
+
+///wells shallower than this are just bumps
+const MIN_WELL_DEPTH: usize = 2;
+
+#[rustfmt::skip]
+const T_SLOT: [&str; 3] = [
+    "#..",
+    "...",
+    "#.#",
+];
+
+pub fn get_height(board: &BitBoard) -> usize {
+    board.stack_height()
 }
 
-pub fn get_garbage_height(board: &Board) -> usize {
-    for y in (0..40).rev() {
-        let mut garbage_found = false;
-        for x in 0..10 {
-            if board[x + y * 10] == MinoType::Garbage {
-                garbage_found = true;
-                break;
+pub fn get_garbage_height(board: &BitBoard) -> usize {
+    (0..board.height())
+        .take_while(|&y| board.garbage_row(y) != 0)
+        .count()
+}
+
+pub fn get_column_heights(board: &BitBoard) -> Vec<usize> {
+    board.column_heights()
+}
+
+pub fn get_well(board: &BitBoard) -> (usize, usize) {
+    let column_heights = get_column_heights(board);
+    let min_height = column_heights
+        .into_iter()
+        .enumerate()
+        .min_by(|(_, a), (_, b)| a.cmp(b))
+        .expect("column_heights empty");
+    min_height
+}
+
+pub fn get_board_features(board: &BitBoard) -> BoardFeatures {
+    let width = board.width();
+    let board_height = board.height();
+    let column_heights = get_column_heights(board);
+    let height = column_heights.iter().copied().max().unwrap_or(0);
+    let filled = |x: usize, y: usize| board.is_filled(x, y);
+
+    let mut features = BoardFeatures {
+        bumpiness: column_heights
+            .windows(2)
+            .map(|pair| pair[0].abs_diff(pair[1]))
+            .sum(),
+        ..Default::default()
+    };
+
+    let mut black: usize = 0;
+    let mut white: usize = 0;
+    for (x, &column_height) in column_heights.iter().enumerate() {
+        let mut cells_above = 0;
+        let mut covered = 0;
+        let mut was_filled = true; //the floor counts as filled
+        for y in (0..column_height).rev() {
+            if filled(x, y) {
+                cells_above += 1;
+                if (x + y) % 2 == 0 {
+                    black += 1;
+                } else {
+                    white += 1;
+                }
+                if y > 0 && !filled(x, y - 1) {
+                    features.overhangs += 1;
+                }
+            } else {
+                features.holes += 1;
+                features.hole_depth += cells_above;
+                covered = cells_above;
+            }
+        }
+        features.covered_cells += covered;
+        for y in 0..column_height {
+            if filled(x, y) != was_filled {
+                features.column_transitions += 1;
             }
+            was_filled = filled(x, y);
         }
-        if !garbage_found {
-            return 39 - y;
+        //the open space above the stack is empty
+        if was_filled {
+            features.column_transitions += 1;
+        }
+
+        let left = if x == 0 {
+            board_height
+        } else {
+            column_heights[x - 1]
+        };
+        let right = column_heights.get(x + 1).copied().unwrap_or(board_height);
+        let depth = left.min(right).saturating_sub(column_height);
+        if depth >= MIN_WELL_DEPTH {
+            features.wells += 1;
+            features.well_depth = features.well_depth.max(depth);
         }
     }
-    0
-}
+    for y in 0..height {
+        //walls count as filled, wide enough to hold a 64 column row with both walls
+        let walled = (board.row(y) as u128) << 1 | 1 | 1 << (width + 1);
+        let changes = (walled ^ walled >> 1) & ((1 << (width + 1)) - 1);
+        features.row_transitions += changes.count_ones() as usize;
+    }
+    features.parity = black.abs_diff(white);
 
-pub fn get_well(board: &Board) -> (usize, usize) {
-    let column_heights: Vec<usize> = (0..10_usize)
-        .map(|x| {
-            for y in 0..40 {
-                if board[x + y * 10] != MinoType::Empty {
-                    return 40 - y;
-                }
-            }
-            0
+    features.t_slots = (0..height.min(board_height.saturating_sub(2)))
+        .flat_map(|y| (0..width.saturating_sub(2)).map(move |x| (x, y)))
+        .filter(|&(x, y)| {
+            matches_pattern(board, &T_SLOT, x, y, false)
+                || matches_pattern(board, &T_SLOT, x, y, true)
         })
-        .collect();
-    let min_height = column_heights.into_iter().enumerate().min_by(|(_, a), (_, b)| a.cmp(b)).expect("column_heights empty");
-    min_height
+        .count();
+
+    features.column_heights = column_heights;
+    features
 }
+
 ///Checks if the top layer of garbage on the board is cheese or not
-pub fn has_cheese(board: &Board) -> bool {
-    let mut last_garbage_col = 10;
+pub fn has_cheese(board: &BitBoard) -> bool {
+    let width = board.width();
+    let mut last_garbage_col = width;
     let mut count = 0;
-    for y in (0..40).rev() {
-        let mut garbage_found = false;
-        let mut new_col = 10;
-        for x in 0..10 {
-            if board[x + y * 10] == MinoType::Garbage {
-                garbage_found = true;
-            } else if board[x + y * 10] == MinoType::Empty {
-                new_col = x;
-            }
-        }
-        if !garbage_found {
+    for y in 0..board.height() {
+        if board.garbage_row(y) == 0 {
             break;
         }
+        let empty = !board.row(y) & board.full_row();
+        let new_col = if empty == 0 {
+            width
+        } else {
+            63 - empty.leading_zeros() as usize
+        };
         if last_garbage_col == new_col {
             count += 1;
         } else {
@@ -70,3 +165,260 @@ pub fn has_cheese(board: &Board) -> bool {
     }
     false
 }
+
+///checks a pattern against the board with its bottom left corner at `x`, `y` counted up from the bottom row
+///
+///patterns are written top row first, `#` is a filled cell, `.` is an empty cell and anything else matches any cell
+pub fn matches_pattern(
+    board: &BitBoard,
+    pattern: &[&str],
+    x: usize,
+    y: usize,
+    mirrored: bool,
+) -> bool {
+    let rows = pattern.len();
+    if y + rows > board.height() {
+        return false;
+    }
+    pattern.iter().enumerate().all(|(row, line)| {
+        let pattern_width = line.len();
+        if x + pattern_width > board.width() {
+            return false;
+        }
+        let board_y = y + rows - 1 - row;
+        line.bytes().enumerate().all(|(col, cell)| {
+            let board_x = if mirrored {
+                x + pattern_width - 1 - col
+            } else {
+                x + col
+            };
+            let filled = board.is_filled(board_x, board_y);
+            match cell {
+                b'#' => filled,
+                b'.' => !filled,
+                _ => true,
+            }
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay_response::Board;
+    use crate::test_support::{board, board_of_width};
+
+    ///the analyzers as they were on unpacked boards, the packed ones have to agree with them
+    mod unpacked {
+        use crate::replay_response::{Board, MinoType};
+
+        pub fn get_height(board: &Board, width: usize) -> usize {
+            let height = board.len() / width;
+            for y in 0..height {
+                for x in 0..width {
+                    if board[x + y * width] != MinoType::Empty {
+                        return height - y;
+                    }
+                }
+            }
+            0
+        }
+
+        pub fn get_garbage_height(board: &Board, width: usize) -> usize {
+            let height = board.len() / width;
+            for y in (0..height).rev() {
+                let mut garbage_found = false;
+                for x in 0..width {
+                    if board[x + y * width] == MinoType::Garbage {
+                        garbage_found = true;
+                        break;
+                    }
+                }
+                if !garbage_found {
+                    return height - 1 - y;
+                }
+            }
+            0
+        }
+
+        pub fn get_column_heights(board: &Board, width: usize) -> Vec<usize> {
+            let height = board.len() / width;
+            (0..width)
+                .map(|x| {
+                    for y in 0..height {
+                        if board[x + y * width] != MinoType::Empty {
+                            return height - y;
+                        }
+                    }
+                    0
+                })
+                .collect()
+        }
+
+        pub fn get_well(board: &Board, width: usize) -> (usize, usize) {
+            get_column_heights(board, width)
+                .into_iter()
+                .enumerate()
+                .min_by(|(_, a), (_, b)| a.cmp(b))
+                .expect("column_heights empty")
+        }
+
+        pub fn has_cheese(board: &Board, width: usize) -> bool {
+            let mut last_garbage_col = width;
+            let mut count = 0;
+            for y in (0..board.len() / width).rev() {
+                let mut garbage_found = false;
+                let mut new_col = width;
+                for x in 0..width {
+                    if board[x + y * width] == MinoType::Garbage {
+                        garbage_found = true;
+                    } else if board[x + y * width] == MinoType::Empty {
+                        new_col = x;
+                    }
+                }
+                if !garbage_found {
+                    break;
+                }
+                if last_garbage_col == new_col {
+                    count += 1;
+                } else {
+                    count = 1;
+                }
+                last_garbage_col = new_col;
+            }
+            count > 0 && count < 4
+        }
+    }
+
+    ///boards of every supported shape with garbage, cheese and holes, paired with their width
+    fn fixed_boards() -> Vec<(Board, usize)> {
+        vec![
+            (board(&[]), 10),
+            (
+                board(&[
+                    "..T.......",
+                    ".TTT...LLL",
+                    "#####.####",
+                    "######.###",
+                    "######.###",
+                ]),
+                10,
+            ),
+            (
+                board(&[
+                    "IIII......",
+                    "###.######",
+                    "###.######",
+                    "###.######",
+                    "###.######",
+                ]),
+                10,
+            ),
+            (board_of_width(4, &["I...", "I.O.", "I#OO", "##.#"]), 4),
+            (
+                board_of_width(
+                    12,
+                    &[
+                        "....SS......",
+                        "...SS.....J.",
+                        "##########..",
+                        "###########.",
+                        "#.##########",
+                    ],
+                ),
+                12,
+            ),
+        ]
+    }
+
+    #[test]
+    fn packed_analyzers_match_the_unpacked_ones() {
+        for (cells, width) in fixed_boards() {
+            let packed = BitBoard::new(&cells, width);
+            assert_eq!(
+                get_height(&packed),
+                unpacked::get_height(&cells, width),
+                "height of {:?}",
+                cells
+            );
+            assert_eq!(
+                get_garbage_height(&packed),
+                unpacked::get_garbage_height(&cells, width),
+                "garbage height of {:?}",
+                cells
+            );
+            assert_eq!(
+                get_column_heights(&packed),
+                unpacked::get_column_heights(&cells, width)
+            );
+            assert_eq!(get_well(&packed), unpacked::get_well(&cells, width));
+            assert_eq!(
+                has_cheese(&packed),
+                unpacked::has_cheese(&cells, width),
+                "cheese of {:?}",
+                cells
+            );
+        }
+    }
+
+    #[test]
+    fn fixed_boards_have_the_expected_garbage() {
+        let garbage: Vec<_> = fixed_boards()
+            .iter()
+            .map(|(cells, width)| {
+                let packed = BitBoard::new(cells, *width);
+                (
+                    get_height(&packed),
+                    get_garbage_height(&packed),
+                    has_cheese(&packed),
+                )
+            })
+            .collect();
+        assert_eq!(
+            garbage,
+            vec![
+                (0, 0, false),
+                (5, 3, true),
+                (5, 4, false),
+                (4, 2, true),
+                (5, 3, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn board_features_of_a_t_slot_stack() {
+        //a tsd slot at column 3 with holes under columns 1 and 4
+        let board = BitBoard::new(
+            &board(&["....L.....", "LL........", "L.L.L.....", "LLL.LL...."]),
+            10,
+        );
+        let features = get_board_features(&board);
+        assert_eq!(features.column_heights, vec![3, 3, 2, 0, 4, 1, 0, 0, 0, 0]);
+        assert_eq!(features.bumpiness, 11);
+        assert_eq!(features.holes, 2);
+        assert_eq!(features.hole_depth, 2);
+        assert_eq!(features.covered_cells, 2);
+        assert_eq!(features.overhangs, 2);
+        assert_eq!(features.row_transitions, 16);
+        assert_eq!(features.column_transitions, 14);
+        assert_eq!(features.wells, 1);
+        assert_eq!(features.well_depth, 2);
+        assert_eq!(features.t_slots, 1);
+        assert_eq!(features.parity, 3);
+    }
+
+    #[test]
+    fn an_empty_board_has_no_features() {
+        let board = BitBoard::new(&board(&[]), 10);
+        let features = get_board_features(&board);
+        assert_eq!(features.bumpiness, 0);
+        assert_eq!(features.holes, 0);
+        assert_eq!(features.row_transitions, 0);
+        //every empty column goes from the floor to open space once
+        assert_eq!(features.column_transitions, 10);
+        assert_eq!(features.wells, 0);
+        assert_eq!(features.t_slots, 0);
+        assert_eq!(features.parity, 0);
+    }
+}
@@ -0,0 +1,118 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+///z score of a two sided 95% interval
+pub const Z_95: f64 = 1.96;
+const BOOTSTRAP_RESAMPLES: usize = 1000;
+///fixed so the same input always gives the same interval
+const BOOTSTRAP_SEED: u64 = 0x9e3779b97f4a7c15;
+
+///a ratio together with how many samples it came from and a 95% confidence interval
+#[derive(Serialize, Deserialize, JsonSchema, Default, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Ratio {
+    pub value: f64,
+    pub samples: usize,
+    pub lower: f64,
+    pub upper: f64,
+}
+
+impl Ratio {
+    ///wilson score interval for `successes` out of `trials`
+    pub fn wilson(successes: usize, trials: usize) -> Self {
+        let n = trials as f64;
+        let p = successes as f64 / n;
+        let z2 = Z_95 * Z_95;
+        let center = (p + z2 / (2.0 * n)) / (1.0 + z2 / n);
+        let margin = Z_95 / (1.0 + z2 / n) * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt();
+        Self {
+            value: p,
+            samples: trials,
+            lower: center - margin,
+            upper: center + margin,
+        }
+    }
+
+    ///a count per unit, like attack per line, treating the count as poisson distributed
+    pub fn poisson(count: usize, units: usize) -> Self {
+        let n = units as f64;
+        let value = count as f64 / n;
+        let margin = Z_95 * (count as f64).sqrt() / n;
+        Self {
+            value,
+            samples: units,
+            lower: (value - margin).max(0.0),
+            upper: value + margin,
+        }
+    }
+
+    ///a count over a stretch of play in counts per minute, treating the count as poisson distributed
+    pub fn per_minute(count: usize, frames: f64, samples: usize) -> Self {
+        let minutes = frames / 3600.0;
+        let value = count as f64 / minutes;
+        let margin = Z_95 * (count as f64).sqrt() / minutes;
+        Self {
+            value,
+            samples,
+            lower: (value - margin).max(0.0),
+            upper: value + margin,
+        }
+    }
+
+    ///the same ratio in other units, like counts per minute as counts per second
+    pub fn scaled(self, factor: f64) -> Self {
+        Self {
+            value: self.value * factor,
+            lower: self.lower * factor,
+            upper: self.upper * factor,
+            ..self
+        }
+    }
+
+    ///ratio of sums over independent samples such as chains, the interval comes from resampling them
+    pub fn bootstrap(samples: &[(f64, f64)]) -> Self {
+        let ratio = |sum: (f64, f64)| sum.0 / sum.1;
+        let add = |a: (f64, f64), b: &(f64, f64)| (a.0 + b.0, a.1 + b.1);
+        let value = ratio(samples.iter().fold((0.0, 0.0), add));
+        if samples.is_empty() {
+            return Self {
+                value,
+                samples: 0,
+                lower: f64::NAN,
+                upper: f64::NAN,
+            };
+        }
+
+        let mut state = BOOTSTRAP_SEED;
+        let mut next = || {
+            //xorshift64
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as usize % samples.len()
+        };
+        let mut resampled: Vec<f64> = (0..BOOTSTRAP_RESAMPLES)
+            .map(|_| {
+                ratio(
+                    (0..samples.len())
+                        .map(|_| &samples[next()])
+                        .fold((0.0, 0.0), add),
+                )
+            })
+            .filter(|r| r.is_finite())
+            .collect();
+        resampled.sort_by(f64::total_cmp);
+        let at = |fraction: f64| {
+            resampled
+                .get((fraction * resampled.len() as f64) as usize)
+                .copied()
+                .unwrap_or(f64::NAN)
+        };
+        Self {
+            value,
+            samples: samples.len(),
+            lower: at(0.025),
+            upper: at(0.975),
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use crate::board_analyzer::BoardFeatures;
+use crate::placement_stats::CumulativePlacementStats;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+///amount of buckets in every histogram, the first and last also hold everything past their edge
+pub const HISTOGRAM_BUCKETS: usize = 10;
+
+///where the buckets of a metric's histogram start and how wide they are
+///
+///the edges are fixed per metric so histograms of different games and players can be compared bucket by bucket
+#[derive(Debug, Clone, Copy)]
+pub struct Buckets {
+    pub start: f64,
+    pub width: f64,
+}
+
+///frames, up to a second
+pub const FRAME_DELAY_BUCKETS: Buckets = Buckets {
+    start: 0.0,
+    width: 6.0,
+};
+const ATTACK_BUCKETS: Buckets = Buckets {
+    start: 0.0,
+    width: 1.0,
+};
+const POTENTIAL_BUCKETS: Buckets = Buckets {
+    start: 0.0,
+    width: 2.0,
+};
+const BLOCKFISH_SCORE_BUCKETS: Buckets = Buckets {
+    start: 0.0,
+    width: 10.0,
+};
+///rows, also used for well depth
+const HEIGHT_BUCKETS: Buckets = Buckets {
+    start: 0.0,
+    width: 2.0,
+};
+///percent of the garbage that is messy
+const MESSINESS_BUCKETS: Buckets = Buckets {
+    start: 0.0,
+    width: 10.0,
+};
+const DEATH_RISK_BUCKETS: Buckets = Buckets {
+    start: 0.0,
+    width: 0.1,
+};
+const BUMPINESS_BUCKETS: Buckets = Buckets {
+    start: 0.0,
+    width: 4.0,
+};
+///also used for overhangs, wells and t slots
+const HOLE_BUCKETS: Buckets = Buckets {
+    start: 0.0,
+    width: 1.0,
+};
+///filled cells, also used for hole depth
+const COVERED_CELL_BUCKETS: Buckets = Buckets {
+    start: 0.0,
+    width: 4.0,
+};
+const ROW_TRANSITION_BUCKETS: Buckets = Buckets {
+    start: 0.0,
+    width: 6.0,
+};
+const COLUMN_TRANSITION_BUCKETS: Buckets = Buckets {
+    start: 0.0,
+    width: 4.0,
+};
+const PARITY_BUCKETS: Buckets = Buckets {
+    start: 0.0,
+    width: 2.0,
+};
+
+#[derive(Serialize, Deserialize, JsonSchema, Default, Debug, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Histogram {
+    pub start: f64,
+    pub bucket_width: f64,
+    pub counts: Vec<usize>,
+}
+
+///spread of a per placement series
+#[derive(Serialize, Deserialize, JsonSchema, Default, Debug, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Distribution {
+    pub count: usize,
+    pub mean: f64,
+    pub sd: f64,
+    pub min: f64,
+    pub p10: f64,
+    pub p25: f64,
+    pub median: f64,
+    pub p75: f64,
+    pub p90: f64,
+    pub max: f64,
+    pub histogram: Histogram,
+}
+
+impl Distribution {
+    ///none for an empty series
+    pub fn new(values: impl IntoIterator<Item = f64>, buckets: Buckets) -> Option<Self> {
+        let mut values: Vec<f64> = values.into_iter().filter(|v| v.is_finite()).collect();
+        if values.is_empty() {
+            return None;
+        }
+        values.sort_by(f64::total_cmp);
+        let count = values.len();
+        let mean = values.iter().sum::<f64>() / count as f64;
+        let sd = (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / count as f64).sqrt();
+        let min = values[0];
+        let max = values[count - 1];
+
+        let mut counts = vec![0; HISTOGRAM_BUCKETS];
+        for value in values.iter() {
+            //negative offsets saturate to the first bucket
+            let bucket = ((value - buckets.start) / buckets.width) as usize;
+            counts[bucket.min(HISTOGRAM_BUCKETS - 1)] += 1;
+        }
+
+        Some(Self {
+            count,
+            mean,
+            sd,
+            min,
+            p10: percentile(&values, 10.0),
+            p25: percentile(&values, 25.0),
+            median: percentile(&values, 50.0),
+            p75: percentile(&values, 75.0),
+            p90: percentile(&values, 90.0),
+            max,
+            histogram: Histogram {
+                start: buckets.start,
+                bucket_width: buckets.width,
+                counts,
+            },
+        })
+    }
+}
+
+///linearly interpolated percentile of sorted values
+fn percentile(sorted: &[f64], percent: f64) -> f64 {
+    let rank = percent / 100.0 * (sorted.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f64)
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Default, Debug)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Distributions {
+    pub frame_delay: Option<Distribution>,
+    pub attack: Option<Distribution>,
+    pub attack_potential: Option<Distribution>,
+    pub defence_potential: Option<Distribution>,
+    pub blockfish_score: Option<Distribution>,
+    pub stack_height: Option<Distribution>,
+    pub garbage_height: Option<Distribution>,
+    pub garbage_messiness: Option<Distribution>,
+    pub death_risk: Option<Distribution>,
+    pub bumpiness: Option<Distribution>,
+    pub holes: Option<Distribution>,
+    pub hole_depth: Option<Distribution>,
+    pub covered_cells: Option<Distribution>,
+    pub overhangs: Option<Distribution>,
+    pub row_transitions: Option<Distribution>,
+    pub column_transitions: Option<Distribution>,
+    pub wells: Option<Distribution>,
+    pub well_depth: Option<Distribution>,
+    pub t_slots: Option<Distribution>,
+    pub parity: Option<Distribution>,
+}
+
+impl From<&CumulativePlacementStats> for Distributions {
+    fn from(stats: &CumulativePlacementStats) -> Self {
+        let usizes = |values: &[usize], buckets: Buckets| {
+            Distribution::new(values.iter().map(|&v| v as f64), buckets)
+        };
+        let features = &stats.board_features;
+        let feature = |value: fn(&BoardFeatures) -> usize, buckets: Buckets| {
+            Distribution::new(features.iter().map(|f| value(f) as f64), buckets)
+        };
+        Self {
+            frame_delay: Distribution::new(stats.delays.iter().copied(), FRAME_DELAY_BUCKETS),
+            attack: usizes(&stats.attacks, ATTACK_BUCKETS),
+            attack_potential: Distribution::new(
+                stats.attack_potentials.iter().flatten().map(|&v| v as f64),
+                POTENTIAL_BUCKETS,
+            ),
+            defence_potential: usizes(&stats.defense_potentials, POTENTIAL_BUCKETS),
+            blockfish_score: usizes(&stats.blockfish_scores, BLOCKFISH_SCORE_BUCKETS),
+            stack_height: usizes(&stats.stack_heights, HEIGHT_BUCKETS),
+            garbage_height: usizes(&stats.garbage_heights, HEIGHT_BUCKETS),
+            garbage_messiness: Distribution::new(
+                stats.garbage_messiness.iter().copied(),
+                MESSINESS_BUCKETS,
+            ),
+            death_risk: Distribution::new(stats.death_risks.iter().copied(), DEATH_RISK_BUCKETS),
+            bumpiness: feature(|f| f.bumpiness, BUMPINESS_BUCKETS),
+            holes: feature(|f| f.holes, HOLE_BUCKETS),
+            hole_depth: feature(|f| f.hole_depth, COVERED_CELL_BUCKETS),
+            covered_cells: feature(|f| f.covered_cells, COVERED_CELL_BUCKETS),
+            overhangs: feature(|f| f.overhangs, HOLE_BUCKETS),
+            row_transitions: feature(|f| f.row_transitions, ROW_TRANSITION_BUCKETS),
+            column_transitions: feature(|f| f.column_transitions, COLUMN_TRANSITION_BUCKETS),
+            wells: feature(|f| f.wells, HOLE_BUCKETS),
+            well_depth: feature(|f| f.well_depth, HEIGHT_BUCKETS),
+            t_slots: feature(|f| f.t_slots, HOLE_BUCKETS),
+            parity: feature(|f| f.parity, PARITY_BUCKETS),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histograms_of_different_series_share_their_edges() {
+        let short = Distribution::new([1.0, 2.0, 3.0], HEIGHT_BUCKETS).unwrap();
+        let tall = Distribution::new([4.0, 12.0, 30.0], HEIGHT_BUCKETS).unwrap();
+        for histogram in [&short.histogram, &tall.histogram] {
+            assert_eq!(histogram.start, 0.0);
+            assert_eq!(histogram.bucket_width, 2.0);
+            assert_eq!(histogram.counts.len(), HISTOGRAM_BUCKETS);
+        }
+        assert_eq!(short.histogram.counts, [1, 2, 0, 0, 0, 0, 0, 0, 0, 0]);
+        //past the last edge lands in the last bucket
+        assert_eq!(tall.histogram.counts, [0, 0, 1, 0, 0, 0, 1, 0, 0, 1]);
+    }
+}
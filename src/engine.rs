@@ -0,0 +1,449 @@
+use crate::replay_response::{Board, ClearType, MinoType};
+use std::collections::VecDeque;
+
+///pieces shown in the preview, also how many are written to a placement's queue
+pub const PREVIEW_PIECES: usize = 5;
+
+///cells of a piece in the spawn orientation, relative to its rotation origin with y pointing up
+pub(crate) fn spawn_cells(shape: MinoType) -> [(i32, i32); 4] {
+    match shape {
+        MinoType::T => [(-1, 0), (0, 0), (1, 0), (0, 1)],
+        MinoType::J => [(-1, 1), (-1, 0), (0, 0), (1, 0)],
+        MinoType::L => [(1, 1), (-1, 0), (0, 0), (1, 0)],
+        MinoType::S => [(-1, 0), (0, 0), (0, 1), (1, 1)],
+        MinoType::Z => [(-1, 1), (0, 1), (0, 0), (1, 0)],
+        MinoType::O => [(0, 0), (1, 0), (0, 1), (1, 1)],
+        MinoType::I => [(-1, 0), (0, 0), (1, 0), (2, 0)],
+        MinoType::Garbage | MinoType::Empty => unreachable!(),
+    }
+}
+
+///cells of a piece in one of the four orientations, 0 is spawn and every step is a clockwise turn
+pub(crate) fn cells(shape: MinoType, rotation: usize) -> [(i32, i32); 4] {
+    let mut cells = spawn_cells(shape);
+    match shape {
+        MinoType::O => {}
+        //the i piece turns around the corner below and right of its origin, done in doubled coordinates
+        MinoType::I => {
+            for cell in cells.iter_mut() {
+                let (x, y) = (cell.0 * 2 - 1, cell.1 * 2 + 1);
+                let (x, y) = (0..rotation).fold((x, y), |(x, y), _| (y, -x));
+                *cell = ((x + 1) / 2, (y - 1) / 2);
+            }
+        }
+        _ => {
+            for cell in cells.iter_mut() {
+                *cell = (0..rotation).fold(*cell, |(x, y), _| (y, -x));
+            }
+        }
+    }
+    cells
+}
+
+///kick offsets tried in order for a turn, indexed by the starting orientation
+type Kicks = [[(i32, i32); 5]; 4];
+
+#[rustfmt::skip]
+const JLSTZ_CW: Kicks = [
+    [(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)],
+    [(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)],
+    [(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)],
+    [(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)],
+];
+#[rustfmt::skip]
+const JLSTZ_CCW: Kicks = [
+    [(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)],
+    [(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)],
+    [(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)],
+    [(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)],
+];
+#[rustfmt::skip]
+const I_CW: Kicks = [
+    [(0, 0), (-2, 0), (1, 0), (-2, -1), (1, 2)],
+    [(0, 0), (-1, 0), (2, 0), (-1, 2), (2, -1)],
+    [(0, 0), (2, 0), (-1, 0), (2, 1), (-1, -2)],
+    [(0, 0), (1, 0), (-2, 0), (1, -2), (-2, 1)],
+];
+#[rustfmt::skip]
+const I_CCW: Kicks = [
+    [(0, 0), (-1, 0), (2, 0), (-1, 2), (2, -1)],
+    [(0, 0), (2, 0), (-1, 0), (2, 1), (-1, -2)],
+    [(0, 0), (1, 0), (-2, 0), (1, -2), (-2, 1)],
+    [(0, 0), (-2, 0), (1, 0), (-2, -1), (1, 2)],
+];
+//srs+ makes the i piece kick the same way in both directions
+#[rustfmt::skip]
+const I_PLUS_CW: Kicks = [
+    [(0, 0), (1, 0), (-2, 0), (-2, -1), (1, 2)],
+    [(0, 0), (-1, 0), (2, 0), (-1, 2), (2, -1)],
+    [(0, 0), (-1, 0), (2, 0), (2, 1), (-1, -2)],
+    [(0, 0), (1, 0), (-2, 0), (1, -2), (-2, 1)],
+];
+#[rustfmt::skip]
+const I_PLUS_CCW: Kicks = [
+    [(0, 0), (-1, 0), (2, 0), (2, -1), (-1, 2)],
+    [(0, 0), (-1, 0), (2, 0), (-1, -2), (2, 1)],
+    [(0, 0), (1, 0), (-2, 0), (1, -2), (-2, 1)],
+    [(0, 0), (1, 0), (-2, 0), (-2, -1), (1, 2)],
+];
+#[rustfmt::skip]
+const HALF_TURN_PLUS: [[(i32, i32); 6]; 4] = [
+    [(0, 0), (0, 1), (1, 1), (-1, 1), (1, 0), (-1, 0)],
+    [(0, 0), (1, 0), (1, 2), (1, 1), (0, 2), (0, 1)],
+    [(0, 0), (0, -1), (-1, -1), (1, -1), (-1, 0), (1, 0)],
+    [(0, 0), (-1, 0), (-1, 2), (-1, 1), (0, 2), (0, 1)],
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RotationSystem {
+    ///guideline srs, 180 turns only succeed in place
+    Srs,
+    ///tetr.io's srs+, symmetric i kicks and a kick table for 180 turns
+    SrsPlus,
+}
+
+impl RotationSystem {
+    fn kicks(&self, shape: MinoType, from: usize, turn: usize) -> Vec<(i32, i32)> {
+        let table = match (self, shape, turn) {
+            (_, MinoType::O, _) => return vec![(0, 0)],
+            (RotationSystem::SrsPlus, _, 2) => return HALF_TURN_PLUS[from].to_vec(),
+            (RotationSystem::Srs, _, 2) => return vec![(0, 0)],
+            (RotationSystem::SrsPlus, MinoType::I, 1) => I_PLUS_CW,
+            (RotationSystem::SrsPlus, MinoType::I, _) => I_PLUS_CCW,
+            (RotationSystem::Srs, MinoType::I, 1) => I_CW,
+            (RotationSystem::Srs, MinoType::I, _) => I_CCW,
+            (_, _, 1) => JLSTZ_CW,
+            _ => JLSTZ_CCW,
+        };
+        table[from].to_vec()
+    }
+}
+
+///turns a seed into an endless sequence of bags
+pub trait Randomizer {
+    fn next_bag(&mut self) -> Vec<MinoType>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Spin {
+    None,
+    Mini,
+    Full,
+}
+
+///the piece under the player's control
+#[derive(Debug, Clone, Copy)]
+struct Falling {
+    shape: MinoType,
+    rotation: usize,
+    x: i32,
+    y: i32,
+    ///index of the kick used by the last successful action, none when the piece moved since
+    last_kick: Option<usize>,
+}
+
+///what happened when a piece locked
+#[derive(Debug, Clone)]
+pub struct Lock {
+    pub shape: MinoType,
+    pub lines_cleared: usize,
+    pub garbage_cleared: usize,
+    pub clear_type: ClearType,
+    pub perfect_clear: bool,
+    ///btb clear that continued an existing chain
+    pub btb_clear: bool,
+    ///combo counter after the lock, 0 on the first clear of a combo
+    pub combo: Option<usize>,
+    ///btb counter after the lock, 0 on the first btb clear of a chain
+    pub btb: Option<usize>,
+}
+
+///a single player's board, queue and falling piece, just enough of a game to replay inputs
+pub struct Engine<R: Randomizer> {
+    width: usize,
+    height: usize,
+    ///rows from the bottom up
+    rows: Vec<Vec<MinoType>>,
+    rotation_system: RotationSystem,
+    randomizer: R,
+    next: VecDeque<MinoType>,
+    hold: Option<MinoType>,
+    hold_locked: bool,
+    piece: Option<Falling>,
+    combo: Option<usize>,
+    btb: Option<usize>,
+    topped_out: bool,
+}
+
+impl<R: Randomizer> Engine<R> {
+    pub fn new(
+        width: usize,
+        height: usize,
+        rotation_system: RotationSystem,
+        randomizer: R,
+    ) -> Self {
+        let mut engine = Self {
+            width,
+            height,
+            rows: vec![vec![MinoType::Empty; width]; height],
+            rotation_system,
+            randomizer,
+            next: VecDeque::new(),
+            hold: None,
+            hold_locked: false,
+            piece: None,
+            combo: None,
+            btb: None,
+            topped_out: false,
+        };
+        engine.spawn_next();
+        engine
+    }
+
+    pub fn topped_out(&self) -> bool {
+        self.topped_out
+    }
+
+    ///the board top row first, like replay responses store it
+    pub fn board(&self) -> Board {
+        self.rows.iter().rev().flatten().copied().collect()
+    }
+
+    ///hold piece followed by the pieces that come next
+    pub fn queue(&self) -> Vec<MinoType> {
+        let mut queue: Vec<_> = self.hold.into_iter().collect();
+        queue.extend(self.piece.map(|piece| piece.shape));
+        queue.extend(self.next.iter().take(PREVIEW_PIECES));
+        queue
+    }
+
+    fn is_free(&self, x: i32, y: i32) -> bool {
+        x >= 0
+            && y >= 0
+            && (x as usize) < self.width
+            && (y as usize >= self.height || self.rows[y as usize][x as usize] == MinoType::Empty)
+    }
+
+    fn fits(&self, shape: MinoType, rotation: usize, x: i32, y: i32) -> bool {
+        cells(shape, rotation)
+            .iter()
+            .all(|&(cx, cy)| self.is_free(x + cx, y + cy))
+    }
+
+    fn spawn(&mut self, shape: MinoType) {
+        let x = (self.width as i32 - 1) / 2;
+        //spawn just above the visible 20 rows, one higher when that is blocked
+        let y = (self.height as i32 / 2 + 1).min(self.height as i32 - 2);
+        let y = if self.fits(shape, 0, x, y) { y } else { y + 1 };
+        self.topped_out |= !self.fits(shape, 0, x, y);
+        self.piece = Some(Falling {
+            shape,
+            rotation: 0,
+            x,
+            y,
+            last_kick: None,
+        });
+    }
+
+    fn spawn_next(&mut self) {
+        while self.next.len() <= PREVIEW_PIECES {
+            let bag = self.randomizer.next_bag();
+            self.next.extend(bag);
+        }
+        let shape = self.next.pop_front().unwrap();
+        self.spawn(shape);
+    }
+
+    ///moves the piece sideways by `dx` cells one at a time, returns how far it got
+    pub fn shift(&mut self, dx: i32) -> i32 {
+        let Some(mut piece) = self.piece else {
+            return 0;
+        };
+        let step = dx.signum();
+        let mut moved = 0;
+        while moved != dx && self.fits(piece.shape, piece.rotation, piece.x + step, piece.y) {
+            piece.x += step;
+            moved += step;
+            piece.last_kick = None;
+        }
+        self.piece = Some(piece);
+        moved
+    }
+
+    ///moves the piece down by up to `dy` cells, returns how far it fell
+    pub fn drop(&mut self, dy: usize) -> usize {
+        let Some(mut piece) = self.piece else {
+            return 0;
+        };
+        let mut fallen = 0;
+        while fallen < dy && self.fits(piece.shape, piece.rotation, piece.x, piece.y - 1) {
+            piece.y -= 1;
+            fallen += 1;
+            piece.last_kick = None;
+        }
+        self.piece = Some(piece);
+        fallen
+    }
+
+    ///turns the piece clockwise `turn` times, trying every kick of the rotation system
+    pub fn rotate(&mut self, turn: usize) -> bool {
+        let Some(mut piece) = self.piece else {
+            return false;
+        };
+        let turn = turn % 4;
+        let rotation = (piece.rotation + turn) % 4;
+        let kicks = self
+            .rotation_system
+            .kicks(piece.shape, piece.rotation, turn);
+        for (i, (kx, ky)) in kicks.into_iter().enumerate() {
+            if self.fits(piece.shape, rotation, piece.x + kx, piece.y + ky) {
+                piece.rotation = rotation;
+                piece.x += kx;
+                piece.y += ky;
+                piece.last_kick = Some(i);
+                self.piece = Some(piece);
+                return true;
+            }
+        }
+        false
+    }
+
+    pub fn hold(&mut self) {
+        let Some(piece) = self.piece else {
+            return;
+        };
+        if self.hold_locked {
+            return;
+        }
+        self.hold_locked = true;
+        match self.hold.replace(piece.shape) {
+            Some(held) => self.spawn(held),
+            None => self.spawn_next(),
+        }
+    }
+
+    ///three corner t-spin check, the fifth kick of a turn always counts as a full spin
+    fn spin(&self, piece: &Falling) -> Spin {
+        if piece.shape != MinoType::T || piece.last_kick.is_none() {
+            return Spin::None;
+        }
+        let filled = |(dx, dy): (i32, i32)| !self.is_free(piece.x + dx, piece.y + dy);
+        let corners = [(-1, 1), (1, 1), (1, -1), (-1, -1)];
+        if corners.iter().filter(|&&c| filled(c)).count() < 3 {
+            return Spin::None;
+        }
+        //the two corners on the side the t points to
+        let front = [
+            corners[piece.rotation % 4],
+            corners[(piece.rotation + 1) % 4],
+        ];
+        if front.iter().all(|&c| filled(c)) || piece.last_kick == Some(4) {
+            Spin::Full
+        } else {
+            Spin::Mini
+        }
+    }
+
+    ///hard drops the piece, clears lines and spawns the next one
+    pub fn hard_drop(&mut self) -> Option<Lock> {
+        self.drop(self.height);
+        let piece = self.piece.take()?;
+        let spin = self.spin(&piece);
+        for (cx, cy) in cells(piece.shape, piece.rotation) {
+            let (x, y) = ((piece.x + cx) as usize, (piece.y + cy) as usize);
+            if y >= self.height {
+                self.topped_out = true;
+                continue;
+            }
+            self.rows[y][x] = piece.shape;
+        }
+
+        let mut lines_cleared = 0;
+        let mut garbage_cleared = 0;
+        self.rows.retain(|row| {
+            let full = row.iter().all(|&mino| mino != MinoType::Empty);
+            if full {
+                lines_cleared += 1;
+                garbage_cleared += row.contains(&MinoType::Garbage) as usize;
+            }
+            !full
+        });
+        self.rows
+            .resize(self.height, vec![MinoType::Empty; self.width]);
+        let perfect_clear = lines_cleared > 0
+            && self
+                .rows
+                .iter()
+                .flatten()
+                .all(|&mino| mino == MinoType::Empty);
+
+        let clear_type = match (spin, lines_cleared) {
+            (Spin::Mini, 0) => ClearType::TspinMini,
+            (Spin::Full, 0) => ClearType::Tspin,
+            (Spin::Mini, 1) => ClearType::TspinMiniSingle,
+            (Spin::Mini, 2) => ClearType::TspinMiniDouble,
+            (Spin::Full, 1) => ClearType::TspinSingle,
+            (Spin::Full, 2) => ClearType::TspinDouble,
+            (_, 3) if spin != Spin::None => ClearType::TspinTriple,
+            (_, 4) if spin != Spin::None => ClearType::TspinQuad,
+            (_, 1) => ClearType::Single,
+            (_, 2) => ClearType::Double,
+            (_, 3) => ClearType::Triple,
+            (_, 4) => ClearType::Quad,
+            (_, 0) => ClearType::None,
+            _ if spin != Spin::None => ClearType::TspinPenta,
+            _ => ClearType::Penta,
+        };
+
+        let mut btb_clear = false;
+        if lines_cleared > 0 {
+            self.combo = Some(self.combo.map_or(0, |combo| combo + 1));
+            if clear_type.is_btb_clear() {
+                btb_clear = self.btb.is_some();
+                self.btb = Some(self.btb.map_or(0, |btb| btb + 1));
+            } else {
+                self.btb = None;
+            }
+        } else {
+            self.combo = None;
+        }
+
+        self.hold_locked = false;
+        self.spawn_next();
+        Some(Lock {
+            shape: piece.shape,
+            lines_cleared,
+            garbage_cleared,
+            clear_type,
+            perfect_clear,
+            btb_clear,
+            combo: self.combo,
+            btb: self.btb,
+        })
+    }
+
+    ///pushes `lines` garbage rows in from the bottom with a hole in `column`, solid rows when it is off the board
+    pub fn add_garbage(&mut self, lines: usize, column: usize) {
+        for _ in 0..lines {
+            let mut row = vec![MinoType::Garbage; self.width];
+            if let Some(hole) = row.get_mut(column) {
+                *hole = MinoType::Empty;
+            }
+            self.rows.insert(0, row);
+            if self
+                .rows
+                .pop()
+                .is_some_and(|row| row.iter().any(|&m| m != MinoType::Empty))
+            {
+                self.topped_out = true;
+            }
+        }
+        //the falling piece gets pushed up with the stack
+        if let Some(mut piece) = self.piece {
+            while !self.fits(piece.shape, piece.rotation, piece.x, piece.y) {
+                piece.y += 1;
+            }
+            self.piece = Some(piece);
+        }
+    }
+}
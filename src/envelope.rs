@@ -0,0 +1,121 @@
+use crate::match_report::GameInput;
+use crate::player_stats::PlayerStats;
+use schemars::{JsonSchema, Schema};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+///version of the input and output formats
+///
+///fields are only ever added with a default so older producers keep working, the version goes up when a field
+///is removed or changes meaning
+pub const FORMAT_VERSION: u32 = 1;
+
+///input or output together with the version of the format it was written in
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Envelope<T> {
+    pub version: u32,
+    pub data: T,
+}
+
+impl<T> Envelope<T> {
+    pub fn new(data: T) -> Self {
+        Self {
+            version: FORMAT_VERSION,
+            data,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct EnvelopeError(pub String);
+impl std::fmt::Display for EnvelopeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("Invalid input: {}", self.0))?;
+        Ok(())
+    }
+}
+impl std::error::Error for EnvelopeError {}
+
+///reads json that is either wrapped in an envelope or bare like before versioning
+///
+///input from a newer version than this one is refused instead of having its new fields dropped
+pub fn read_versioned<T: DeserializeOwned>(json: &str) -> Result<T, EnvelopeError> {
+    let mut value: Value =
+        serde_json::from_str(json).map_err(|error| EnvelopeError(error.to_string()))?;
+    let enveloped = value.as_object().is_some_and(|object| {
+        object.len() == 2 && object.contains_key("version") && object.contains_key("data")
+    });
+    if enveloped {
+        let version = value["version"]
+            .as_u64()
+            .ok_or_else(|| EnvelopeError("version isn't a number".to_string()))?;
+        if version > FORMAT_VERSION as u64 {
+            return Err(EnvelopeError(format!(
+                "format version {} is newer than the supported version {}",
+                version, FORMAT_VERSION
+            )));
+        }
+        value = value["data"].take();
+    }
+    serde_json::from_value(value).map_err(|error| EnvelopeError(error.to_string()))
+}
+
+///json schema of what the evaluator reads, an envelope around a list of games
+pub fn input_schema() -> Schema {
+    schemars::schema_for!(Envelope<Vec<GameInput>>)
+}
+
+///json schema of what `analyze_versioned` writes, `analyze` and `analyze_with_min_samples` write its data bare
+pub fn output_schema() -> Schema {
+    schemars::schema_for!(Envelope<PlayerStats>)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::placement_stats::CumulativePlacementStats;
+
+    fn json_type(value: &Value) -> &'static str {
+        match value {
+            Value::Null => "null",
+            Value::Bool(_) => "boolean",
+            Value::Number(number) if number.is_f64() => "number",
+            Value::Number(_) => "integer",
+            Value::String(_) => "string",
+            Value::Array(_) => "array",
+            Value::Object(_) => "object",
+        }
+    }
+
+    #[test]
+    fn stats_without_games_match_the_output_schema() {
+        //every ratio is 0/0 here, which is where NaN used to come out as null
+        let stats = PlayerStats::new(&CumulativePlacementStats::default(), 0);
+        let output = serde_json::to_value(Envelope::new(&stats)).unwrap();
+        let schema = serde_json::to_value(output_schema()).unwrap();
+        let properties = &schema["$defs"]["PlayerStats"]["properties"];
+
+        for (field, value) in output["data"].as_object().unwrap() {
+            let property = &properties[field];
+            assert!(!property.is_null(), "{} isn't in the schema", field);
+            let allowed: Vec<_> = match &property["type"] {
+                Value::String(kind) => vec![kind.as_str()],
+                Value::Array(kinds) => kinds.iter().filter_map(Value::as_str).collect(),
+                //references to other definitions
+                _ => continue,
+            };
+            let kind = json_type(value);
+            assert!(
+                allowed.contains(&kind) || kind == "integer" && allowed.contains(&"number"),
+                "{} is {} but the schema allows {:?}",
+                field,
+                kind,
+                allowed
+            );
+        }
+
+        let read: PlayerStats = read_versioned(&output.to_string()).unwrap();
+        assert_eq!(serde_json::to_value(Envelope::new(&read)).unwrap(), output);
+    }
+}
@@ -0,0 +1,334 @@
+use crate::match_report::GameInput;
+use crate::placement_stats::CumulativePlacementStats;
+use crate::replay_response::{ClearType, MinoType};
+use serde::Serialize;
+use std::io::Write;
+
+#[derive(Debug)]
+pub struct ExportError(pub String);
+impl std::fmt::Display for ExportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("Invalid export: {}", self.0))?;
+        Ok(())
+    }
+}
+impl std::error::Error for ExportError {}
+
+///one placement with everything the game loop works out for it, attacks are summed over their lines
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PlacementRow {
+    ///index of the game in the input
+    pub game: usize,
+    pub game_id: Option<String>,
+    ///index of the placement in its game
+    pub placement: usize,
+    pub shape: MinoType,
+    pub lines_cleared: usize,
+    pub garbage_cleared: usize,
+    pub keypresses: usize,
+    pub attack: usize,
+    pub clear_type: ClearType,
+    pub combo: usize,
+    pub btb_chain: usize,
+    pub btb_clear: bool,
+    pub frame_delay: f64,
+    pub attack_received: usize,
+    pub attack_tanked: usize,
+    pub board_width: usize,
+    pub board_height: usize,
+    pub stack_height: usize,
+    pub garbage_height: usize,
+    pub well: Option<usize>,
+    pub cheese: bool,
+    pub attack_potential: Option<usize>,
+    pub defence_potential: Option<usize>,
+    pub blockfish_score: Option<usize>,
+    pub death_risk: f64,
+    ///combo and btb segments are numbered from 0 in every game
+    pub combo_segment: Option<usize>,
+    pub btb_segment: Option<usize>,
+}
+
+///a row for every placement of every game
+pub fn placement_rows(games: &[GameInput]) -> Vec<PlacementRow> {
+    let mut rows = Vec::new();
+    for (game_index, game) in games.iter().enumerate() {
+        let placements = game.placements();
+        let stats = CumulativePlacementStats::from(placements);
+        let game_id = game.meta().map(|meta| meta.game_id.clone());
+        for (i, (placement, features)) in
+            placements.iter().zip(stats.placement_features).enumerate()
+        {
+            rows.push(PlacementRow {
+                game: game_index,
+                game_id: game_id.clone(),
+                placement: i,
+                shape: placement.shape,
+                lines_cleared: placement.lines_cleared,
+                garbage_cleared: placement.garbage_cleared,
+                keypresses: placement.keypresses,
+                attack: placement.attack.iter().sum(),
+                clear_type: placement.clear_type,
+                combo: placement.combo,
+                btb_chain: placement.btb_chain,
+                btb_clear: placement.btb_clear,
+                frame_delay: placement.frame_delay,
+                attack_received: placement.attack_received.iter().sum(),
+                attack_tanked: placement.attack_tanked.iter().sum(),
+                board_width: placement.board_width,
+                board_height: placement.board_height,
+                stack_height: features.stack_height,
+                garbage_height: features.garbage_height,
+                well: features.well,
+                cheese: features.cheese,
+                attack_potential: features.attack_potential,
+                defence_potential: features.defence_potential,
+                blockfish_score: features.blockfish_score,
+                death_risk: features.death_risk,
+                combo_segment: features.combo_segment,
+                btb_segment: features.btb_segment,
+            });
+        }
+    }
+    rows
+}
+
+///rows as csv with a header, missing values are empty
+pub fn write_csv<W: Write>(rows: &[PlacementRow], writer: W) -> Result<(), ExportError> {
+    let mut writer = csv::Writer::from_writer(writer);
+    for row in rows {
+        writer
+            .serialize(row)
+            .map_err(|error| ExportError(error.to_string()))?;
+    }
+    writer
+        .flush()
+        .map_err(|error| ExportError(error.to_string()))
+}
+
+#[cfg(feature = "parquet")]
+enum Column {
+    Int(Vec<Option<i64>>),
+    Double(Vec<Option<f64>>),
+    Bool(Vec<Option<bool>>),
+    Text(Vec<Option<String>>),
+}
+
+///the rows split into columns named like the csv header
+#[cfg(feature = "parquet")]
+fn columns(rows: &[PlacementRow]) -> Vec<(&'static str, Column)> {
+    let int = |value: fn(&PlacementRow) -> Option<usize>| {
+        Column::Int(
+            rows.iter()
+                .map(|row| value(row).map(|v| v as i64))
+                .collect(),
+        )
+    };
+    let double = |value: fn(&PlacementRow) -> f64| {
+        Column::Double(rows.iter().map(|row| Some(value(row))).collect())
+    };
+    let boolean = |value: fn(&PlacementRow) -> bool| {
+        Column::Bool(rows.iter().map(|row| Some(value(row))).collect())
+    };
+    let text =
+        |value: fn(&PlacementRow) -> Option<String>| Column::Text(rows.iter().map(value).collect());
+    vec![
+        ("game", int(|row| Some(row.game))),
+        ("gameId", text(|row| row.game_id.clone())),
+        ("placement", int(|row| Some(row.placement))),
+        ("shape", int(|row| Some(row.shape as usize))),
+        ("linesCleared", int(|row| Some(row.lines_cleared))),
+        ("garbageCleared", int(|row| Some(row.garbage_cleared))),
+        ("keypresses", int(|row| Some(row.keypresses))),
+        ("attack", int(|row| Some(row.attack))),
+        (
+            "clearType",
+            text(|row| {
+                let clear_type = serde_json::to_value(row.clear_type).ok()?;
+                clear_type.as_str().map(String::from)
+            }),
+        ),
+        ("combo", int(|row| Some(row.combo))),
+        ("btbChain", int(|row| Some(row.btb_chain))),
+        ("btbClear", boolean(|row| row.btb_clear)),
+        ("frameDelay", double(|row| row.frame_delay)),
+        ("attackReceived", int(|row| Some(row.attack_received))),
+        ("attackTanked", int(|row| Some(row.attack_tanked))),
+        ("boardWidth", int(|row| Some(row.board_width))),
+        ("boardHeight", int(|row| Some(row.board_height))),
+        ("stackHeight", int(|row| Some(row.stack_height))),
+        ("garbageHeight", int(|row| Some(row.garbage_height))),
+        ("well", int(|row| row.well)),
+        ("cheese", boolean(|row| row.cheese)),
+        ("attackPotential", int(|row| row.attack_potential)),
+        ("defencePotential", int(|row| row.defence_potential)),
+        ("blockfishScore", int(|row| row.blockfish_score)),
+        ("deathRisk", double(|row| row.death_risk)),
+        ("comboSegment", int(|row| row.combo_segment)),
+        ("btbSegment", int(|row| row.btb_segment)),
+    ]
+}
+
+///values that are present and the definition level of every row, 0 for a missing value
+#[cfg(feature = "parquet")]
+fn present<T: Clone>(values: &[Option<T>]) -> (Vec<T>, Vec<i16>) {
+    let levels = values.iter().map(|value| value.is_some() as i16).collect();
+    (values.iter().flatten().cloned().collect(), levels)
+}
+
+///rows as a single row group parquet file with the same columns as the csv
+#[cfg(feature = "parquet")]
+pub fn write_parquet<W: Write + Send>(rows: &[PlacementRow], writer: W) -> Result<(), ExportError> {
+    use parquet::data_type::{BoolType, ByteArray, ByteArrayType, DoubleType, Int64Type};
+    use parquet::file::properties::WriterProperties;
+    use parquet::file::writer::SerializedFileWriter;
+    use parquet::schema::parser::parse_message_type;
+    use std::sync::Arc;
+
+    let error = |error: parquet::errors::ParquetError| ExportError(error.to_string());
+    let columns = columns(rows);
+    let fields: String = columns
+        .iter()
+        .map(|(name, column)| match column {
+            Column::Int(_) => format!("OPTIONAL INT64 {};", name),
+            Column::Double(_) => format!("OPTIONAL DOUBLE {};", name),
+            Column::Bool(_) => format!("OPTIONAL BOOLEAN {};", name),
+            Column::Text(_) => format!("OPTIONAL BYTE_ARRAY {} (UTF8);", name),
+        })
+        .collect();
+    let schema =
+        parse_message_type(&format!("message placement {{ {} }}", fields)).map_err(error)?;
+
+    let mut file = SerializedFileWriter::new(
+        writer,
+        Arc::new(schema),
+        Arc::new(WriterProperties::builder().build()),
+    )
+    .map_err(error)?;
+    let mut row_group = file.next_row_group().map_err(error)?;
+    for (_, column) in &columns {
+        let mut writer = row_group
+            .next_column()
+            .map_err(error)?
+            .ok_or_else(|| ExportError("schema is missing a column".to_string()))?;
+        match column {
+            Column::Int(values) => {
+                let (values, levels) = present(values);
+                writer
+                    .typed::<Int64Type>()
+                    .write_batch(&values, Some(&levels), None)
+            }
+            Column::Double(values) => {
+                let (values, levels) = present(values);
+                writer
+                    .typed::<DoubleType>()
+                    .write_batch(&values, Some(&levels), None)
+            }
+            Column::Bool(values) => {
+                let (values, levels) = present(values);
+                writer
+                    .typed::<BoolType>()
+                    .write_batch(&values, Some(&levels), None)
+            }
+            Column::Text(values) => {
+                let (values, levels) = present(values);
+                let values: Vec<_> = values
+                    .iter()
+                    .map(|value| ByteArray::from(value.as_str()))
+                    .collect();
+                writer
+                    .typed::<ByteArrayType>()
+                    .write_batch(&values, Some(&levels), None)
+            }
+        }
+        .map_err(error)?;
+        writer.close().map_err(error)?;
+    }
+    row_group.close().map_err(error)?;
+    file.close().map_err(error)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::match_report::{Game, GameMeta};
+    use crate::replay_response::PlacementStats;
+    use crate::test_support::{board, placement};
+
+    fn game() -> Vec<GameInput> {
+        vec![GameInput::Game(Game {
+            meta: GameMeta {
+                game_id: "game".to_string(),
+                ..Default::default()
+            },
+            placements: vec![
+                placement(),
+                PlacementStats {
+                    shape: MinoType::L,
+                    lines_cleared: 2,
+                    keypresses: 4,
+                    attack: vec![1],
+                    clear_type: ClearType::Double,
+                    combo: 1,
+                    frame_delay: 12.5,
+                    attack_received: vec![2, 1],
+                    attack_tanked: vec![2],
+                    board: board(&["LL........", "#####.####", "######.###"]),
+                    ..placement()
+                },
+            ],
+        })]
+    }
+
+    fn csv(rows: &[PlacementRow]) -> csv::Reader<std::io::Cursor<Vec<u8>>> {
+        let mut data = Vec::new();
+        write_csv(rows, &mut data).unwrap(); //writing to memory
+        csv::Reader::from_reader(std::io::Cursor::new(data))
+    }
+
+    #[test]
+    fn a_row_has_the_placement_and_its_board() {
+        let rows = placement_rows(&game());
+        assert_eq!(rows.len(), 2);
+        let row = &rows[1];
+        assert_eq!(row.game, 0);
+        assert_eq!(row.game_id.as_deref(), Some("game"));
+        assert_eq!(row.placement, 1);
+        assert_eq!(row.attack, 1);
+        assert_eq!(row.attack_received, 3);
+        assert_eq!(row.attack_tanked, 2);
+        assert_eq!(row.stack_height, 1);
+        assert_eq!(row.garbage_height, 2);
+        assert!(row.cheese);
+        assert_eq!(row.well, None);
+        assert_eq!(row.combo_segment, Some(0));
+
+        let mut reader = csv(&rows);
+        let header = reader.headers().unwrap().clone(); //written above
+        let record = reader.records().nth(1).unwrap().unwrap(); //second row
+        let value = |name: &str| {
+            let column = header.iter().position(|h| h == name).unwrap(); //every field is a column
+            record[column].to_string()
+        };
+        assert_eq!(value("gameId"), "game");
+        assert_eq!(value("shape"), (MinoType::L as usize).to_string());
+        assert_eq!(value("clearType"), "DOUBLE");
+        assert_eq!(value("frameDelay"), "12.5");
+        assert_eq!(value("attackReceived"), "3");
+        assert_eq!(value("cheese"), "true");
+        //missing values are left empty
+        assert_eq!(value("well"), "");
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn csv_header_matches_the_parquet_columns() {
+        let rows = placement_rows(&game());
+        let mut reader = csv(&rows);
+        let header: Vec<_> = reader.headers().unwrap().iter().map(String::from).collect(); //written above
+        let columns: Vec<_> = columns(&rows).iter().map(|(name, _)| *name).collect();
+        assert_eq!(header, columns);
+    }
+}
@@ -0,0 +1,383 @@
+use crate::engine::spawn_cells;
+use crate::replay_response::{Board, MinoType, DEFAULT_BOARD_HEIGHT};
+
+const FUMEN_PREFIX: &str = "v115@";
+const FUMEN_CHARS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+///fumen fields are always 10 wide
+pub const FUMEN_WIDTH: usize = 10;
+///rows shown in the editor, a hidden garbage row sits below them
+const VISIBLE_ROWS: usize = 23;
+const FIELD_BLOCKS: usize = FUMEN_WIDTH * (VISIBLE_ROWS + 1);
+///a diff of 8 is an unchanged cell
+const UNCHANGED: usize = 8;
+///the editor breaks long data with a `?` after the first 42 characters and every 47 after that
+const FIRST_LINE: usize = 42;
+const LINE: usize = 47;
+
+///cells of a field, row 0 is the top and the last row is the garbage row
+type Field = [u8; FIELD_BLOCKS];
+
+#[derive(Debug)]
+pub struct FumenError(pub String);
+impl std::fmt::Display for FumenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("Invalid fumen: {}", self.0))?;
+        Ok(())
+    }
+}
+impl std::error::Error for FumenError {}
+
+fn to_fumen_block(mino: MinoType) -> u8 {
+    match mino {
+        MinoType::Empty => 0,
+        MinoType::I => 1,
+        MinoType::L => 2,
+        MinoType::O => 3,
+        MinoType::Z => 4,
+        MinoType::T => 5,
+        MinoType::J => 6,
+        MinoType::S => 7,
+        MinoType::Garbage => 8,
+    }
+}
+
+fn from_fumen_block(block: u8) -> MinoType {
+    match block {
+        1 => MinoType::I,
+        2 => MinoType::L,
+        3 => MinoType::O,
+        4 => MinoType::Z,
+        5 => MinoType::T,
+        6 => MinoType::J,
+        7 => MinoType::S,
+        8 => MinoType::Garbage,
+        _ => MinoType::Empty,
+    }
+}
+
+///the bottom 23 rows of a board stored top row first, anything higher can't be shown
+fn board_to_field(board: &Board) -> Field {
+    let height = board.len() / FUMEN_WIDTH;
+    let mut field = [0; FIELD_BLOCKS];
+    for row in 0..VISIBLE_ROWS.min(height) {
+        for x in 0..FUMEN_WIDTH {
+            let mino = board[(height - 1 - row) * FUMEN_WIDTH + x];
+            field[(VISIBLE_ROWS - 1 - row) * FUMEN_WIDTH + x] = to_fumen_block(mino);
+        }
+    }
+    field
+}
+
+fn field_to_board(field: &Field) -> Board {
+    let mut board = vec![MinoType::Empty; FUMEN_WIDTH * DEFAULT_BOARD_HEIGHT];
+    let offset = (DEFAULT_BOARD_HEIGHT - VISIBLE_ROWS) * FUMEN_WIDTH;
+    for (i, &block) in field[..VISIBLE_ROWS * FUMEN_WIDTH].iter().enumerate() {
+        board[offset + i] = from_fumen_block(block);
+    }
+    board
+}
+
+fn push_value(data: &mut Vec<u8>, mut value: usize, chars: usize) {
+    for _ in 0..chars {
+        data.push(FUMEN_CHARS[value % 64]);
+        value /= 64;
+    }
+}
+
+///encodes boards as the pages of a fumen, each board has to be 10 wide and is stored top row first
+///
+///pages only hold their field, the next page starts from the previous one with full lines cleared
+pub fn encode(boards: &[Board]) -> Option<String> {
+    if boards
+        .iter()
+        .any(|board| board.is_empty() || board.len() % FUMEN_WIDTH != 0)
+    {
+        return None;
+    }
+    let mut data = Vec::new();
+    let mut previous = [0; FIELD_BLOCKS];
+    //index of the count of unchanged pages that follow the last unchanged field
+    let mut repeat: Option<usize> = None;
+    for (page, board) in boards.iter().enumerate() {
+        let field = board_to_field(board);
+        let diffs: Vec<_> = field
+            .iter()
+            .zip(previous.iter())
+            .map(|(&current, &previous)| current as usize + UNCHANGED - previous as usize)
+            .collect();
+        let changed = diffs.iter().any(|&diff| diff != UNCHANGED);
+        match repeat {
+            Some(index) if !changed && data[index] != FUMEN_CHARS[63] => {
+                let count = FUMEN_CHARS.iter().position(|&c| c == data[index]).unwrap(); //only fumen chars are written
+                data[index] = FUMEN_CHARS[count + 1];
+            }
+            _ => {
+                let mut start = 0;
+                while start < diffs.len() {
+                    let run = diffs[start..]
+                        .iter()
+                        .take_while(|&&diff| diff == diffs[start])
+                        .count();
+                    push_value(&mut data, diffs[start] * FIELD_BLOCKS + run - 1, 2);
+                    start += run;
+                }
+                repeat = if changed {
+                    None
+                } else {
+                    data.push(FUMEN_CHARS[0]);
+                    Some(data.len() - 1)
+                };
+            }
+        }
+        //no piece, locked, guideline colours turned on with the first page
+        let colorize = (page == 0) as usize;
+        push_value(&mut data, colorize * 4 * FIELD_BLOCKS * 32, 3);
+        previous = field;
+        clear_lines(&mut previous);
+    }
+
+    let data = String::from_utf8(data).unwrap(); //only fumen chars are written
+    let mut fumen = FUMEN_PREFIX.to_string();
+    fumen.push_str(&data[..data.len().min(FIRST_LINE)]);
+    let rest = data.as_bytes().get(FIRST_LINE..).unwrap_or_default();
+    for line in rest.chunks(LINE) {
+        fumen.push('?');
+        fumen.push_str(std::str::from_utf8(line).unwrap()); //split between ascii characters
+    }
+    Some(fumen)
+}
+
+///removes full rows from the visible part of a field, the garbage row is never cleared
+fn clear_lines(field: &mut Field) {
+    let rows: Vec<_> = field[..VISIBLE_ROWS * FUMEN_WIDTH]
+        .chunks(FUMEN_WIDTH)
+        .filter(|row| row.contains(&0))
+        .flatten()
+        .copied()
+        .collect();
+    let cleared = VISIBLE_ROWS * FUMEN_WIDTH - rows.len();
+    field[..cleared].fill(0);
+    field[cleared..VISIBLE_ROWS * FUMEN_WIDTH].copy_from_slice(&rows);
+}
+
+struct Reader<'a> {
+    data: std::slice::Iter<'a, u8>,
+}
+
+impl Reader<'_> {
+    fn is_empty(&self) -> bool {
+        self.data.len() == 0
+    }
+
+    fn poll(&mut self, chars: usize) -> Result<usize, FumenError> {
+        let mut value = 0;
+        let mut scale = 1;
+        for _ in 0..chars {
+            let c = *self
+                .data
+                .next()
+                .ok_or_else(|| FumenError("data ended early".to_string()))?;
+            let digit = FUMEN_CHARS
+                .iter()
+                .position(|&fumen_char| fumen_char == c)
+                .ok_or_else(|| FumenError(format!("unexpected character {}", c as char)))?;
+            value += digit * scale;
+            scale *= 64;
+        }
+        Ok(value)
+    }
+}
+
+///field cells a piece covers given the origin and rotation written in a page
+///
+///the editor rotates every piece around its origin cell and shifts some origins, see knewjade's tetris-fumen
+fn piece_cells(block: u8, rotation: usize, coordinate: usize) -> Vec<usize> {
+    let shape = from_fumen_block(block);
+    let mut x = (coordinate % FUMEN_WIDTH) as i32;
+    let mut y = (VISIBLE_ROWS - 1) as i32 - (coordinate / FUMEN_WIDTH) as i32;
+    //fumen orders rotations reverse, right, spawn, left
+    let rotation = [2, 1, 0, 3][rotation];
+    match (shape, rotation) {
+        (MinoType::O, 3) => {
+            x += 1;
+            y -= 1;
+        }
+        (MinoType::O, 2) | (MinoType::I, 2) | (MinoType::Z, 3) => x += 1,
+        (MinoType::O, 0) | (MinoType::I, 3) | (MinoType::S, 0) | (MinoType::Z, 0) => y -= 1,
+        (MinoType::S, 1) => x -= 1,
+        _ => {}
+    }
+    spawn_cells(shape)
+        .iter()
+        .map(|&cell| (0..rotation).fold(cell, |(x, y), _| (y, -x)))
+        .map(|(cx, cy)| (x + cx, y + cy))
+        .filter(|&(x, y)| {
+            (0..FUMEN_WIDTH as i32).contains(&x) && (-1..VISIBLE_ROWS as i32).contains(&y)
+        })
+        .map(|(x, y)| (VISIBLE_ROWS as i32 - 1 - y) as usize * FUMEN_WIDTH + x as usize)
+        .collect()
+}
+
+///decodes every page of a v115 fumen into a 10 wide board of the default height, stored top row first
+///
+///the field of each page is returned without the page's piece, pieces only show up once locked into the next page
+pub fn decode(fumen: &str) -> Result<Vec<Board>, FumenError> {
+    let start = fumen
+        .find(FUMEN_PREFIX)
+        .ok_or_else(|| FumenError("not a v115 fumen".to_string()))?;
+    let data: Vec<u8> = fumen[start + FUMEN_PREFIX.len()..]
+        .bytes()
+        .filter(|&c| c != b'?' && !c.is_ascii_whitespace())
+        .collect();
+    let mut reader = Reader { data: data.iter() };
+
+    let mut boards = Vec::new();
+    let mut previous: Field = [0; FIELD_BLOCKS];
+    let mut repeat = 0;
+    while !reader.is_empty() {
+        let mut field = previous;
+        if repeat > 0 {
+            repeat -= 1;
+        } else {
+            let mut i = 0;
+            while i < FIELD_BLOCKS {
+                let value = reader.poll(2)?;
+                let diff = value / FIELD_BLOCKS;
+                let run = value % FIELD_BLOCKS + 1;
+                if i + run > FIELD_BLOCKS || diff > 2 * UNCHANGED {
+                    return Err(FumenError("field runs past the end".to_string()));
+                }
+                for block in &mut field[i..i + run] {
+                    *block = (*block as usize + diff)
+                        .checked_sub(UNCHANGED)
+                        .filter(|&block| block <= 8)
+                        .ok_or_else(|| FumenError("block out of range".to_string()))?
+                        as u8;
+                }
+                i += run;
+            }
+            if field == previous {
+                repeat = reader.poll(1)?;
+            }
+        }
+
+        let mut action = reader.poll(3)?;
+        let block = (action % 8) as u8;
+        action /= 8;
+        let rotation = action % 4;
+        action /= 4;
+        let coordinate = action % FIELD_BLOCKS;
+        action /= FIELD_BLOCKS;
+        let [rise, mirror, _colorize, comment, not_locked] =
+            [0, 1, 2, 3, 4].map(|bit| action >> bit & 1 == 1);
+        if comment {
+            //comments are skipped, every 5 characters hold 4 letters
+            let length = reader.poll(2)?;
+            for _ in 0..length.div_ceil(4) {
+                reader.poll(5)?;
+            }
+        }
+        boards.push(field_to_board(&field));
+
+        if !not_locked {
+            if block != 0 {
+                for cell in piece_cells(block, rotation, coordinate) {
+                    field[cell] = block;
+                }
+            }
+            clear_lines(&mut field);
+            if rise {
+                field.copy_within(FUMEN_WIDTH.., 0);
+                field[VISIBLE_ROWS * FUMEN_WIDTH..].fill(0);
+            }
+            if mirror {
+                for row in field[..VISIBLE_ROWS * FUMEN_WIDTH].chunks_mut(FUMEN_WIDTH) {
+                    row.reverse();
+                }
+            }
+        }
+        previous = field;
+    }
+    if boards.is_empty() {
+        return Err(FumenError("no pages".to_string()));
+    }
+    Ok(boards)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::board;
+
+    #[test]
+    fn empty_field_matches_the_editor() {
+        //what the editor gives for a new fumen
+        assert_eq!(decode("v115@vhAAgH").unwrap(), [board(&[])]);
+        assert_eq!(encode(&[board(&[])]).unwrap(), "v115@vhAAgH");
+    }
+
+    #[test]
+    fn field_runs_decode_cell_by_cell() {
+        //220 empty cells, 9 garbage, then 11 empty cells including the hidden garbage row
+        let field = "v115@bhI8KeAgH";
+        assert_eq!(decode(field).unwrap(), [board(&["#########."])]);
+        assert_eq!(encode(&[board(&["#########."])]).unwrap(), field);
+    }
+
+    #[test]
+    fn locked_piece_shows_up_on_the_next_page() {
+        //a t in spawn orientation with its origin at x 4 on the bottom row, then a page that repeats the field
+        let pages = decode("v115@vhAVQJvhAAAA").unwrap();
+        assert_eq!(pages, [board(&[]), board(&["....T.....", "...TTT...."])]);
+    }
+
+    #[test]
+    fn every_rotation_covers_four_cells_around_its_origin() {
+        //an origin in the middle of the field, nothing gets cut off by the walls
+        let coordinate = 12 * FUMEN_WIDTH + 4;
+        for block in 1..=7 {
+            for rotation in 0..4 {
+                let mut cells = piece_cells(block, rotation, coordinate);
+                cells.sort();
+                cells.dedup();
+                assert_eq!(cells.len(), 4, "block {} rotation {}", block, rotation);
+                let (x, y) = (coordinate % FUMEN_WIDTH, coordinate / FUMEN_WIDTH);
+                assert!(cells.iter().all(|&cell| {
+                    (cell % FUMEN_WIDTH).abs_diff(x) <= 2 && (cell / FUMEN_WIDTH).abs_diff(y) <= 2
+                }));
+            }
+        }
+    }
+
+    #[test]
+    fn pages_round_trip() {
+        let boards = vec![
+            board(&["##.#######"]),
+            board(&["I.........", "I.........", "I.........", "I#.#######"]),
+            //the same field again is stored as a repeat of the last page
+            board(&["I.........", "I.........", "I.........", "I#.#######"]),
+            board(&["I.........", "I.........", "I.........", "I#.#######"]),
+            //a full row is cleared before the next page is compared with it
+            board(&[
+                "I.........",
+                "I.........",
+                "I.SS......",
+                "ISS.......",
+                "##########",
+            ]),
+            board(&["T.........", "TT........", "T.........", ".........."]),
+        ];
+        let mut pages = boards.clone();
+        //long data is broken into lines
+        pages.extend(boards.iter().rev().cloned());
+        let fumen = encode(&pages).unwrap();
+        assert!(fumen.contains('?'));
+        assert_eq!(decode(&fumen).unwrap(), pages);
+    }
+
+    #[test]
+    fn boards_that_arent_10_wide_are_refused() {
+        assert_eq!(encode(&[vec![MinoType::Empty; 44]]), None);
+        assert!(decode("not a fumen").is_err());
+    }
+}
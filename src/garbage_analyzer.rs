@@ -0,0 +1,201 @@
+use crate::bitboard::BitBoard;
+use serde::{Deserialize, Serialize};
+
+///segments with at least this many rows sharing a hole are clean garbage, anything shorter is cheese
+pub const CLEAN_SEGMENT_ROWS: usize = 4;
+
+///consecutive garbage rows that share the same hole column
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GarbageSegment {
+    ///lowest row of the segment, counted up from the bottom of the board
+    pub bottom: usize,
+    pub rows: usize,
+    ///none when the rows have more than one hole
+    pub hole_column: Option<usize>,
+    pub clean: bool,
+    ///placement that cleared the last row of the segment, none while it stays on the board
+    pub dug_at: Option<usize>,
+}
+
+///breakdown of the garbage at the bottom of a single board
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GarbageAnalysis {
+    ///ordered from the bottom of the board up
+    pub segments: Vec<GarbageSegment>,
+    pub rows: usize,
+    pub clean_rows: usize,
+    pub messy_rows: usize,
+    pub hole_columns: Vec<usize>,
+    ///percentage of the garbage rows that are part of messy segments, none without garbage
+    pub messiness: Option<f64>,
+    ///received garbage still waiting to rise, filled in by the game loop
+    pub incoming: usize,
+    ///incoming garbage the placement cancelled, filled in by the game loop
+    pub cancelled: usize,
+}
+
+fn hole_column(board: &BitBoard, y: usize) -> Option<usize> {
+    let empty = !board.row(y) & board.full_row();
+    if empty.count_ones() == 1 {
+        Some(empty.trailing_zeros() as usize)
+    } else {
+        None
+    }
+}
+
+pub fn analyze_garbage(board: &BitBoard) -> GarbageAnalysis {
+    let mut analysis = GarbageAnalysis::default();
+    for y in (0..board.height()).take_while(|&y| board.garbage_row(y) != 0) {
+        let hole = hole_column(board, y);
+        match analysis.segments.last_mut() {
+            Some(segment) if hole.is_some() && segment.hole_column == hole => segment.rows += 1,
+            _ => analysis.segments.push(GarbageSegment {
+                bottom: y,
+                rows: 1,
+                hole_column: hole,
+                clean: false,
+                dug_at: None,
+            }),
+        }
+        if let Some(hole) = hole {
+            if !analysis.hole_columns.contains(&hole) {
+                analysis.hole_columns.push(hole);
+            }
+        }
+        analysis.rows += 1;
+    }
+    for segment in analysis.segments.iter_mut() {
+        segment.clean = segment.rows >= CLEAN_SEGMENT_ROWS;
+        if segment.clean {
+            analysis.clean_rows += segment.rows;
+        } else {
+            analysis.messy_rows += segment.rows;
+        }
+    }
+    analysis.messiness =
+        (analysis.rows > 0).then(|| analysis.messy_rows as f64 / analysis.rows as f64 * 100.0);
+    analysis
+}
+
+///fills in `dug_at` for the board after placement `index`, `garbage_cleared` is what every placement of the game
+///cleared
+///
+///clears dig from the top of the garbage down like in `dug_segments`, garbage rising later goes in underneath
+pub fn mark_dug(analysis: &mut GarbageAnalysis, index: usize, garbage_cleared: &[usize]) {
+    let mut later = garbage_cleared.iter().enumerate().skip(index + 1);
+    let mut cleared = 0;
+    let mut depth = 0;
+    let mut dug_at = None;
+    for segment in analysis.segments.iter_mut().rev() {
+        depth += segment.rows;
+        while cleared < depth {
+            let Some((i, &rows)) = later.next() else {
+                return;
+            };
+            cleared += rows;
+            dug_at = Some(i);
+        }
+        segment.dug_at = dug_at;
+    }
+}
+
+///segments that a clear of `garbage_cleared` rows dug through completely, from the top down
+pub fn dug_segments(
+    analysis: &GarbageAnalysis,
+    garbage_cleared: usize,
+) -> impl Iterator<Item = &GarbageSegment> {
+    let mut remaining = garbage_cleared;
+    analysis.segments.iter().rev().take_while(move |segment| {
+        if remaining >= segment.rows {
+            remaining -= segment.rows;
+            true
+        } else {
+            false
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::board;
+
+    ///4 clean rows under 2 rows of cheese
+    fn cheese_on_clean() -> GarbageAnalysis {
+        let board = board(&[
+            "##.#######",
+            "#.########",
+            "###.######",
+            "###.######",
+            "###.######",
+            "###.######",
+        ]);
+        analyze_garbage(&BitBoard::new(&board, 10))
+    }
+
+    #[test]
+    fn garbage_splits_into_clean_and_messy_segments() {
+        let garbage = cheese_on_clean();
+        let segments: Vec<_> = garbage
+            .segments
+            .iter()
+            .map(|segment| {
+                (
+                    segment.bottom,
+                    segment.rows,
+                    segment.hole_column,
+                    segment.clean,
+                )
+            })
+            .collect();
+        assert_eq!(
+            segments,
+            [
+                (0, 4, Some(3), true),
+                (4, 1, Some(1), false),
+                (5, 1, Some(2), false)
+            ]
+        );
+        assert_eq!(garbage.rows, 6);
+        assert_eq!(garbage.clean_rows, 4);
+        assert_eq!(garbage.messy_rows, 2);
+        assert_eq!(garbage.hole_columns, [3, 1, 2]);
+        assert_eq!(garbage.messiness, Some(2.0 / 6.0 * 100.0));
+    }
+
+    #[test]
+    fn boards_without_garbage_have_no_messiness() {
+        let garbage = analyze_garbage(&BitBoard::new(&board(&["LLL......."]), 10));
+        assert!(garbage.segments.is_empty());
+        assert_eq!(garbage.messiness, None);
+    }
+
+    #[test]
+    fn segments_are_dug_from_the_top_down() {
+        let mut garbage = cheese_on_clean();
+        mark_dug(&mut garbage, 0, &[0, 1, 0, 3, 2]);
+        let dug: Vec<_> = garbage
+            .segments
+            .iter()
+            .map(|segment| segment.dug_at)
+            .collect();
+        assert_eq!(dug, [Some(4), Some(3), Some(1)]);
+
+        //the clean rows are still on the board at the end
+        let mut garbage = cheese_on_clean();
+        mark_dug(&mut garbage, 0, &[0, 1, 0, 3]);
+        let dug: Vec<_> = garbage
+            .segments
+            .iter()
+            .map(|segment| segment.dug_at)
+            .collect();
+        assert_eq!(dug, [None, Some(3), Some(1)]);
+
+        let dug: Vec<_> = dug_segments(&garbage, 2)
+            .map(|segment| segment.rows)
+            .collect();
+        assert_eq!(dug, [1, 1]);
+    }
+}
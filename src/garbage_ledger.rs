@@ -0,0 +1,158 @@
+use crate::replay_response::PlacementStats;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+///a chunk of incoming garbage, followed from when it was received until it was cancelled or dug out
+///
+///every position is the index of a placement in its game
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GarbageChunk {
+    pub received_at: usize,
+    pub amount: usize,
+    pub cancelled: usize,
+    pub tanked: usize,
+    pub tanked_at: Option<usize>,
+    pub dug_at: Option<usize>,
+}
+
+impl GarbageChunk {
+    ///placements between the chunk entering the board and its last row being cleared
+    pub fn dig_time(&self) -> Option<usize> {
+        Some(self.dug_at? - self.tanked_at?)
+    }
+
+    fn pending(&self) -> usize {
+        self.amount - self.cancelled - self.tanked
+    }
+}
+
+///follows garbage through a game one placement at a time
+#[derive(Debug, Default)]
+pub struct GarbageLedger {
+    chunks: Vec<GarbageChunk>,
+    ///chunks that still have garbage waiting to be cancelled or tanked, oldest first
+    pending: VecDeque<usize>,
+    ///chunks with rows on the board and how many are left, the oldest is at the top of the garbage
+    ///
+    ///a chunk tanked in several parts has an entry for every part
+    on_board: VecDeque<(usize, usize)>,
+    ///rows of every chunk still on the board over all its parts
+    rows_left: Vec<usize>,
+}
+
+impl GarbageLedger {
+    ///follows the garbage through placement `index`, returns how much incoming garbage its attack cancelled
+    pub fn record(&mut self, index: usize, placement: &PlacementStats) -> usize {
+        for &amount in placement.attack_received.iter().filter(|&&a| a > 0) {
+            self.pending.push_back(self.chunks.len());
+            self.rows_left.push(0);
+            self.chunks.push(GarbageChunk {
+                received_at: index,
+                amount,
+                cancelled: 0,
+                tanked: 0,
+                tanked_at: None,
+                dug_at: None,
+            });
+        }
+
+        //clears happen before anything new rises, so they dig out the oldest garbage on the board
+        let mut cleared = placement.garbage_cleared;
+        while cleared > 0 {
+            let Some((chunk, rows)) = self.on_board.front_mut() else {
+                break;
+            };
+            let dug = cleared.min(*rows);
+            *rows -= dug;
+            cleared -= dug;
+            let chunk = *chunk;
+            self.rows_left[chunk] -= dug;
+            if *rows == 0 {
+                self.on_board.pop_front();
+            }
+            //only dug out once none of it is on the board or still waiting to rise
+            if self.rows_left[chunk] == 0 && self.chunks[chunk].pending() == 0 {
+                self.chunks[chunk].dug_at = Some(index);
+            }
+        }
+
+        let mut attack = placement.attack.iter().sum::<usize>();
+        let mut cancelled_lines = 0;
+        while attack > 0 {
+            let Some(&chunk) = self.pending.front() else {
+                break;
+            };
+            let chunk = &mut self.chunks[chunk];
+            let cancelled = attack.min(chunk.pending());
+            chunk.cancelled += cancelled;
+            attack -= cancelled;
+            cancelled_lines += cancelled;
+            if chunk.pending() == 0 {
+                self.pending.pop_front();
+            }
+        }
+
+        for &amount in placement.attack_tanked.iter() {
+            let mut amount = amount;
+            while amount > 0 {
+                let Some(&chunk_index) = self.pending.front() else {
+                    break;
+                };
+                let chunk = &mut self.chunks[chunk_index];
+                let tanked = amount.min(chunk.pending());
+                chunk.tanked += tanked;
+                chunk.tanked_at.get_or_insert(index);
+                amount -= tanked;
+                self.rows_left[chunk_index] += tanked;
+                self.on_board.push_back((chunk_index, tanked));
+                if chunk.pending() == 0 {
+                    self.pending.pop_front();
+                }
+            }
+        }
+        cancelled_lines
+    }
+
+    ///garbage received that hasn't been cancelled or tanked yet
+    pub fn pending(&self) -> usize {
+        self.pending
+            .iter()
+            .map(|&chunk| self.chunks[chunk].pending())
+            .sum()
+    }
+
+    pub fn finish(self) -> Vec<GarbageChunk> {
+        self.chunks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    fn placement(received: &[usize], cleared: usize, tanked: &[usize]) -> PlacementStats {
+        PlacementStats {
+            lines_cleared: cleared,
+            garbage_cleared: cleared,
+            attack_received: received.to_vec(),
+            attack_tanked: tanked.to_vec(),
+            ..test_support::placement()
+        }
+    }
+
+    #[test]
+    fn chunk_tanked_in_parts_is_dug_with_its_last_row() {
+        let mut ledger = GarbageLedger::default();
+        //8 lines arrive but the garbage cap lets only 4 rise at a time
+        ledger.record(0, &placement(&[8], 0, &[4]));
+        ledger.record(1, &placement(&[], 4, &[4]));
+        ledger.record(2, &placement(&[], 2, &[]));
+        assert_eq!(ledger.chunks[0].dug_at, None);
+        ledger.record(3, &placement(&[], 2, &[]));
+        let chunks = ledger.finish();
+        assert_eq!(chunks[0].tanked, 8);
+        assert_eq!(chunks[0].dug_at, Some(3));
+        assert_eq!(chunks[0].dig_time(), Some(3));
+    }
+}
@@ -0,0 +1,429 @@
+use crate::engine::{Engine, Lock, Randomizer, RotationSystem};
+use crate::match_report::{Game, GameInput, GameMeta};
+use crate::replay_import::{GarbageRules, Recorder, ReplayError};
+use crate::replay_response::{ClearType, MinoType, DEFAULT_BOARD_HEIGHT, DEFAULT_BOARD_WIDTH};
+use serde_json::Value;
+
+///jstris times actions in milliseconds, placements are timed in frames
+const FRAMES_PER_MS: f64 = 60.0 / 1000.0;
+///jstris only records garbage once it rises, so nothing ever waits in the queue
+const JSTRIS_GARBAGE: GarbageRules = GarbageRules {
+    delay: 0.0,
+    cap: usize::MAX,
+};
+const COMBO_TABLE: [usize; 13] = [0, 0, 1, 1, 1, 2, 2, 3, 3, 4, 4, 4, 5];
+const PERFECT_CLEAR_ATTACK: usize = 10;
+
+///piece order of jstris' block ids
+const JSTRIS_PIECES: [MinoType; 7] = [
+    MinoType::I,
+    MinoType::O,
+    MinoType::T,
+    MinoType::L,
+    MinoType::J,
+    MinoType::S,
+    MinoType::Z,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+enum Action {
+    MoveLeft,
+    MoveRight,
+    DasLeft,
+    DasRight,
+    RotateLeft,
+    RotateRight,
+    Rotate180,
+    HardDrop,
+    SoftDropToggle,
+    GravityStep,
+    Hold,
+    GarbageAdd,
+    SolidGarbageAdd,
+    RedbarSet,
+    ArrMove,
+    Aux,
+}
+
+impl std::convert::TryFrom<u8> for Action {
+    type Error = crate::replay_response::OutOfBoundsError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Action::MoveLeft),
+            1 => Ok(Action::MoveRight),
+            2 => Ok(Action::DasLeft),
+            3 => Ok(Action::DasRight),
+            4 => Ok(Action::RotateLeft),
+            5 => Ok(Action::RotateRight),
+            6 => Ok(Action::Rotate180),
+            7 => Ok(Action::HardDrop),
+            8 => Ok(Action::SoftDropToggle),
+            9 => Ok(Action::GravityStep),
+            10 => Ok(Action::Hold),
+            11 => Ok(Action::GarbageAdd),
+            12 => Ok(Action::SolidGarbageAdd),
+            13 => Ok(Action::RedbarSet),
+            14 => Ok(Action::ArrMove),
+            15 => Ok(Action::Aux),
+            _ => Err(crate::replay_response::OutOfBoundsError(value)),
+        }
+    }
+}
+
+///johannes baagøe's alea, the rng jstris seeds from the replay's seed string
+struct Alea {
+    s0: f64,
+    s1: f64,
+    s2: f64,
+    c: f64,
+}
+
+///2^-32
+const ALEA_NORM: f64 = 2.3283064365386963e-10;
+
+struct Mash(f64);
+
+impl Mash {
+    fn mash(&mut self, data: &str) -> f64 {
+        let to_uint32 = |x: f64| (x as u64 & 0xffff_ffff) as f64;
+        for code in data.encode_utf16() {
+            self.0 += code as f64;
+            let mut h = 0.02519603282416938 * self.0;
+            self.0 = to_uint32(h);
+            h -= self.0;
+            h *= self.0;
+            self.0 = to_uint32(h);
+            h -= self.0;
+            self.0 += h * 4294967296.0;
+        }
+        to_uint32(self.0) * ALEA_NORM
+    }
+}
+
+impl Alea {
+    ///every seed is mashed into the state in turn
+    fn new(seeds: &[&str]) -> Self {
+        let mut mash = Mash(0xefc8249d_u32 as f64);
+        let mut alea = Self {
+            s0: mash.mash(" "),
+            s1: mash.mash(" "),
+            s2: mash.mash(" "),
+            c: 1.0,
+        };
+        for seed in seeds {
+            for s in [&mut alea.s0, &mut alea.s1, &mut alea.s2] {
+                *s -= mash.mash(seed);
+                if *s < 0.0 {
+                    *s += 1.0;
+                }
+            }
+        }
+        alea
+    }
+
+    fn next(&mut self) -> f64 {
+        let t = 2091639.0 * self.s0 + self.c * ALEA_NORM;
+        self.s0 = self.s1;
+        self.s1 = self.s2;
+        self.c = t.trunc();
+        self.s2 = t - self.c;
+        self.s2
+    }
+}
+
+///jstris' 7 bag, every piece is drawn at random from what is left of the bag
+pub struct JstrisRandomizer {
+    rng: Alea,
+}
+
+impl JstrisRandomizer {
+    pub fn new(seed: &str) -> Self {
+        Self {
+            rng: Alea::new(&[seed]),
+        }
+    }
+}
+
+impl Randomizer for JstrisRandomizer {
+    fn next_bag(&mut self) -> Vec<MinoType> {
+        let mut remaining = JSTRIS_PIECES.to_vec();
+        let mut bag = Vec::with_capacity(remaining.len());
+        while !remaining.is_empty() {
+            let i = (self.rng.next() * remaining.len() as f64) as usize;
+            bag.push(remaining.remove(i));
+        }
+        bag
+    }
+}
+
+fn jstris_attack(lock: &Lock) -> usize {
+    let base = match lock.clear_type {
+        ClearType::Double | ClearType::TspinMiniDouble => 1,
+        ClearType::Triple | ClearType::TspinSingle => 2,
+        ClearType::Quad | ClearType::TspinDouble | ClearType::Penta => 4,
+        ClearType::TspinTriple | ClearType::TspinQuad | ClearType::TspinPenta => 6,
+        _ => 0,
+    };
+    let combo = COMBO_TABLE[lock.combo.unwrap_or(0).min(COMBO_TABLE.len() - 1)];
+    let perfect_clear = if lock.perfect_clear {
+        PERFECT_CLEAR_ATTACK
+    } else {
+        0
+    };
+    base + lock.btb_clear as usize + combo + perfect_clear
+}
+
+fn decode_base64(data: &str) -> Result<Vec<u8>, ReplayError> {
+    let value = |c: u8| match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' | b'-' => Some(62),
+        b'/' | b'_' => Some(63),
+        _ => None,
+    };
+    let mut bytes = Vec::with_capacity(data.len() * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in data
+        .bytes()
+        .filter(|&c| c != b'=' && !c.is_ascii_whitespace())
+    {
+        let value = value(c).ok_or_else(|| ReplayError(format!("bad base64 byte {}", c)))?;
+        buffer = buffer << 6 | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+        }
+    }
+    Ok(bytes)
+}
+
+///reads a jstris replay, the decompressed json with the game config in `c` and the actions in `d`
+///
+///replays jstris hands out are lz-string compressed, they have to be decompressed before they get here
+///
+///actions are base64 encoded 4 byte big endian words, the top 28 bits are milliseconds since the start
+///and the low 4 bits the action. garbage is followed by a word with the lines in its top 16 bits and
+///the hole column in the bottom 16.
+pub fn import_jstris_replay(json: &str) -> Result<GameInput, ReplayError> {
+    let replay: Value = serde_json::from_str(json).map_err(|error| {
+        if json.trim_start().starts_with('{') {
+            ReplayError(error.to_string())
+        } else {
+            ReplayError("not json, compressed replays have to be decompressed first".to_string())
+        }
+    })?;
+    let config = &replay["c"];
+    let seed = config["seed"]
+        .as_str()
+        .ok_or_else(|| ReplayError("config has no seed".to_string()))?;
+    let data = replay["d"]
+        .as_str()
+        .ok_or_else(|| ReplayError("no action data".to_string()))?;
+    let bytes = decode_base64(data)?;
+    let mut words = bytes
+        .chunks_exact(4)
+        .map(|word| u32::from_be_bytes([word[0], word[1], word[2], word[3]]));
+
+    let engine = Engine::new(
+        DEFAULT_BOARD_WIDTH,
+        DEFAULT_BOARD_HEIGHT,
+        RotationSystem::Srs,
+        JstrisRandomizer::new(seed),
+    );
+    let mut recorder = Recorder::new(engine, JSTRIS_GARBAGE, jstris_attack);
+    let mut soft_drop = false;
+    let mut das_direction = 1;
+
+    while let Some(word) = words.next() {
+        let frame = (word >> 4) as f64 * FRAMES_PER_MS;
+        let Ok(action) = Action::try_from((word & 0xf) as u8) else {
+            continue;
+        };
+        match action {
+            Action::MoveLeft | Action::MoveRight => {
+                recorder.key_pressed();
+                das_direction = if action == Action::MoveLeft { -1 } else { 1 };
+                recorder.engine().shift(das_direction);
+            }
+            Action::DasLeft | Action::DasRight => {
+                recorder.key_pressed();
+                das_direction = if action == Action::DasLeft { -1 } else { 1 };
+                recorder
+                    .engine()
+                    .shift(das_direction * DEFAULT_BOARD_WIDTH as i32);
+            }
+            Action::ArrMove => {
+                recorder.engine().shift(das_direction);
+            }
+            Action::RotateLeft => {
+                recorder.key_pressed();
+                recorder.engine().rotate(3);
+            }
+            Action::RotateRight => {
+                recorder.key_pressed();
+                recorder.engine().rotate(1);
+            }
+            Action::Rotate180 => {
+                recorder.key_pressed();
+                recorder.engine().rotate(2);
+            }
+            //jstris records each row a soft drop moves as a gravity step
+            Action::SoftDropToggle => {
+                soft_drop = !soft_drop;
+                if soft_drop {
+                    recorder.key_pressed();
+                }
+            }
+            Action::GravityStep => {
+                recorder.engine().drop(1);
+            }
+            Action::Hold => {
+                recorder.key_pressed();
+                recorder.engine().hold();
+            }
+            Action::HardDrop => {
+                recorder.key_pressed();
+                recorder.hard_drop(frame);
+            }
+            Action::GarbageAdd | Action::SolidGarbageAdd => {
+                let garbage = words.next().unwrap_or(0);
+                let lines = (garbage >> 16) as usize;
+                let column = if action == Action::SolidGarbageAdd {
+                    DEFAULT_BOARD_WIDTH
+                } else {
+                    (garbage & 0xffff) as usize
+                };
+                recorder.rise(lines, column);
+            }
+            Action::RedbarSet | Action::Aux => {}
+        }
+        if recorder.topped_out() {
+            break;
+        }
+    }
+
+    Ok(GameInput::Game(Game {
+        meta: GameMeta {
+            game_id: format!("jstris-{}", seed),
+            mode: config["m"].as_u64().map(|mode| format!("jstris-{}", mode)),
+            ..Default::default()
+        },
+        placements: recorder.finish(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn encode_base64(bytes: &[u8]) -> String {
+        const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        bytes
+            .chunks(3)
+            .flat_map(|chunk| {
+                let buffer = chunk
+                    .iter()
+                    .chain([0, 0].iter())
+                    .take(3)
+                    .fold(0u32, |buffer, &byte| buffer << 8 | byte as u32);
+                (0..=chunk.len())
+                    .map(move |i| ALPHABET[(buffer >> (18 - 6 * i) & 63) as usize] as char)
+            })
+            .collect()
+    }
+
+    fn word(ms: u32, action: Action) -> u32 {
+        ms << 4 | action as u32
+    }
+
+    fn pieces(bag: Vec<MinoType>) -> String {
+        bag.iter().map(|mino| format!("{:?}", mino)).collect()
+    }
+
+    #[test]
+    fn alea_matches_the_reference() {
+        //the example from alea's readme
+        let mut alea = Alea::new(&["my", "3", "seeds"]);
+        assert_eq!(
+            [alea.next(), alea.next(), alea.next()],
+            [0.30802189325913787, 0.5190450621303171, 0.43635262292809784]
+        );
+    }
+
+    #[test]
+    fn bags_follow_the_seed() {
+        let mut randomizer = JstrisRandomizer::new("abc123");
+        assert_eq!(pieces(randomizer.next_bag()), "SZTOIJL");
+        assert_eq!(pieces(randomizer.next_bag()), "SITZOLJ");
+    }
+
+    #[test]
+    fn attack_adds_btb_combo_and_perfect_clears() {
+        let mut lock = Lock {
+            shape: MinoType::T,
+            lines_cleared: 2,
+            garbage_cleared: 0,
+            clear_type: ClearType::TspinDouble,
+            perfect_clear: false,
+            btb_clear: true,
+            combo: Some(2),
+            btb: Some(1),
+        };
+        assert_eq!(jstris_attack(&lock), 4 + 1 + 1);
+        lock.perfect_clear = true;
+        assert_eq!(jstris_attack(&lock), 4 + 1 + 1 + PERFECT_CLEAR_ATTACK);
+    }
+
+    #[test]
+    fn actions_are_timed_and_garbage_rises() {
+        let words = [
+            word(500, Action::GarbageAdd),
+            //two lines with the hole in column 3
+            2 << 16 | 3,
+            word(1000, Action::HardDrop),
+        ];
+        let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_be_bytes()).collect();
+        let replay = json!({ "c": { "seed": "abc123" }, "d": encode_base64(&bytes) });
+
+        let GameInput::Game(game) = import_jstris_replay(&replay.to_string()).unwrap() else {
+            panic!("a jstris replay is a single game");
+        };
+        assert_eq!(game.meta.game_id, "jstris-abc123");
+        let [placement] = &game.placements[..] else {
+            panic!("one hard drop is one placement");
+        };
+        assert_eq!(placement.shape, MinoType::S);
+        assert_eq!(placement.frame_delay, 60.0);
+        assert_eq!(placement.keypresses, 1);
+        assert_eq!(placement.attack_received, [2]);
+        assert_eq!(placement.attack_tanked, [2]);
+        let bottom: Vec<String> = placement.board[placement.board.len() - 40..]
+            .chunks(DEFAULT_BOARD_WIDTH)
+            .map(|row| {
+                row.iter()
+                    .map(|&mino| match mino {
+                        MinoType::Empty => '.',
+                        MinoType::Garbage => '#',
+                        _ => 'S',
+                    })
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            bottom,
+            ["....SS....", "...SS.....", "###.######", "###.######"]
+        );
+    }
+
+    #[test]
+    fn compressed_replays_are_an_error() {
+        let error = import_jstris_replay("N4IgzgpgTgLgBA").unwrap_err();
+        assert!(error.0.contains("decompressed"));
+    }
+}
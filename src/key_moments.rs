@@ -0,0 +1,189 @@
+use crate::match_report::GameInput;
+use crate::placement_stats::{round_delay, CumulativePlacementStats};
+use crate::replay_response::PlacementStats;
+use crate::review::{attack_sent_after, missed_spike};
+use serde::Serialize;
+
+///moments kept of every kind when no amount is asked for
+pub const DEFAULT_KEY_MOMENTS: usize = 5;
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyMoment {
+    ///index of the placement the moment starts at
+    pub placement: usize,
+    ///seconds into the game once that piece was placed
+    pub time: f64,
+    ///placements the moment lasts
+    pub placements: usize,
+    ///size of the moment, what it measures depends on its kind
+    pub value: f64,
+}
+
+#[derive(Serialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyMoments {
+    ///combos by the attack they sent
+    pub spikes: Vec<KeyMoment>,
+    ///placements by their delay in frames
+    pub hesitations: Vec<KeyMoment>,
+    ///runs of boards that could have spiked by the most attack left unsent on any of them
+    pub missed_attack: Vec<KeyMoment>,
+    ///placements by how much defence potential they lost from the board before
+    pub defence_collapses: Vec<KeyMoment>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GameKeyMoments {
+    pub game_id: Option<String>,
+    #[serde(flatten)]
+    pub moments: KeyMoments,
+}
+
+///the `count` biggest moments, earlier ones first on ties, moments of size 0 aren't worth showing
+fn top(mut moments: Vec<KeyMoment>, count: usize) -> Vec<KeyMoment> {
+    moments.retain(|moment| moment.value > 0.0);
+    moments.sort_by(|a, b| b.value.total_cmp(&a.value));
+    moments.truncate(count);
+    moments
+}
+
+///the `count` biggest moments of every kind in a single game
+pub fn key_moments(game: &[PlacementStats], count: usize) -> KeyMoments {
+    moments(game, &CumulativePlacementStats::from(game), count)
+}
+
+fn moments(game: &[PlacementStats], stats: &CumulativePlacementStats, count: usize) -> KeyMoments {
+    let times: Vec<f64> = stats
+        .delays
+        .iter()
+        .scan(0.0, |frames, delay| {
+            *frames += delay;
+            Some(*frames / 60.0)
+        })
+        .collect();
+    let moment = |placement: usize, placements: usize, value: f64| KeyMoment {
+        placement,
+        time: times[placement],
+        placements,
+        value,
+    };
+    let features = &stats.placement_features;
+
+    //segments are numbered in order so the first placement in each one is where it starts
+    let mut combo_starts = Vec::new();
+    for (i, feature) in features.iter().enumerate() {
+        if feature.combo_segment == Some(combo_starts.len()) {
+            combo_starts.push(i);
+        }
+    }
+    let spikes = stats
+        .combo_segments
+        .iter()
+        .zip(combo_starts)
+        .map(|(combo, start)| moment(start, combo.blocks, combo.attack as f64))
+        .collect();
+
+    let hesitations = game
+        .iter()
+        .enumerate()
+        .map(|(i, placement)| moment(i, 1, round_delay(placement.frame_delay)))
+        .collect();
+
+    //every board of a missed spike misses about as much, so a run only counts once from where it starts
+    let mut missed_attack: Vec<KeyMoment> = Vec::new();
+    let mut missing_spike = false;
+    for (i, feature) in features.iter().enumerate() {
+        let potential = feature.attack_potential.unwrap_or(0);
+        let sent = attack_sent_after(game, i);
+        let missed = missed_spike(potential, sent);
+        let unsent = potential.saturating_sub(sent) as f64;
+        if missed && missing_spike {
+            let run = missed_attack.last_mut().unwrap(); //the run started on an earlier board
+            run.placements += 1;
+            run.value = run.value.max(unsent);
+        } else if missed {
+            missed_attack.push(moment(i, 1, unsent));
+        }
+        missing_spike = missed;
+    }
+
+    let defence_collapses = features
+        .windows(2)
+        .enumerate()
+        .filter_map(|(i, pair)| {
+            let lost = pair[0]
+                .defence_potential?
+                .saturating_sub(pair[1].defence_potential?);
+            Some(moment(i + 1, 1, lost as f64))
+        })
+        .collect();
+
+    KeyMoments {
+        spikes: top(spikes, count),
+        hesitations: top(hesitations, count),
+        missed_attack: top(missed_attack, count),
+        defence_collapses: top(defence_collapses, count),
+    }
+}
+
+pub fn key_moments_games(games: &[GameInput], count: usize) -> Vec<GameKeyMoments> {
+    games
+        .iter()
+        .map(|game| GameKeyMoments {
+            game_id: game.meta().map(|meta| meta.game_id.clone()),
+            moments: key_moments(game.placements(), count),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    fn placement(attack: &[usize]) -> PlacementStats {
+        PlacementStats {
+            frame_delay: 60.0,
+            lines_cleared: attack.len(),
+            attack: attack.to_vec(),
+            ..test_support::placement()
+        }
+    }
+
+    #[test]
+    fn spikes_start_where_their_combo_starts() {
+        let game = [
+            placement(&[]),
+            placement(&[2]),
+            placement(&[4]),
+            placement(&[]),
+            placement(&[1]),
+        ];
+        let moments = key_moments(&game, DEFAULT_KEY_MOMENTS);
+        let spikes: Vec<_> = moments
+            .spikes
+            .iter()
+            .map(|spike| (spike.placement, spike.time, spike.placements, spike.value))
+            .collect();
+        assert_eq!(spikes, [(1, 2.0, 2, 6.0), (4, 5.0, 1, 1.0)]);
+    }
+
+    #[test]
+    fn a_missed_spike_counts_once_for_its_run() {
+        let game: Vec<_> = (0..6).map(|_| placement(&[])).collect();
+        let mut stats = CumulativePlacementStats::from(game.as_slice());
+        let potentials = [0, 10, 11, 10, 0, 12];
+        for (feature, potential) in stats.placement_features.iter_mut().zip(potentials) {
+            feature.attack_potential = Some(potential);
+        }
+        let moments = moments(&game, &stats, DEFAULT_KEY_MOMENTS);
+        let missed: Vec<_> = moments
+            .missed_attack
+            .iter()
+            .map(|moment| (moment.placement, moment.placements, moment.value))
+            .collect();
+        assert_eq!(missed, [(5, 1, 12.0), (1, 3, 11.0)]);
+    }
+}
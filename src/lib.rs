@@ -1,4 +1,6 @@
 mod attack;
+pub mod binary;
+mod bitboard;
 mod placement_stats;
 mod player_stats;
 use placement_stats::CumulativePlacementStats;
@@ -6,9 +8,40 @@ use player_stats::PlayerStats;
 use std::ffi::{CStr, CString};
 use std::os::raw::c_char;
 mod board_analyzer;
+mod confidence;
+mod distribution;
+mod engine;
+pub mod envelope;
+pub mod export;
+mod fumen;
+mod garbage_analyzer;
+mod garbage_ledger;
+mod jstris;
+pub mod key_moments;
+mod match_report;
+mod openers;
+mod pc_finder;
+pub mod position;
+mod rating;
+mod replay_import;
 mod replay_response;
+pub mod review;
+mod setups;
 mod solver;
-use replay_response::PlacementStats;
+pub mod stats_diff;
+#[cfg(test)]
+mod test_support;
+mod tetrio;
+mod time_series;
+mod versus;
+use bitboard::BitBoard;
+pub use match_report::GameInput;
+use match_report::MatchReport;
+use position::{parse_board, parse_queue, PositionError};
+use replay_response::BoardSizeError;
+pub use replay_response::{Board, MinoType};
+use serde::Serialize;
+use time_series::{time_series, GameTimeSeries, Window};
 use tokio::{
     io::{AsyncBufReadExt, BufReader},
     task::JoinSet,
@@ -19,23 +52,71 @@ pub extern "C" fn add(left: usize, right: usize) -> usize {
     left + right
 }
 
-#[no_mangle]
-pub extern "C" fn analyze(arr: *mut *mut c_char, size: usize) -> *const libc::c_char {
-    let mut cumulative_stats = CumulativePlacementStats::default();
+fn read_c_str(ptr: *const c_char) -> String {
+    let c_str = unsafe { CStr::from_ptr(ptr) };
+    c_str.to_string_lossy().into_owned()
+}
+
+///an object with only an `error`, what callers get back for input that can't be read
+fn error_json(error: impl std::fmt::Display) -> String {
+    serde_json::json!({ "error": error.to_string() }).to_string()
+}
+
+///json of the result for input that comes straight from a user, see `error_json` for when it can't be read
+fn result_json<T: Serialize, E: std::fmt::Display>(result: Result<T, E>) -> String {
+    match result {
+        Ok(value) => serde_json::to_string(&value).unwrap(), //plain data always serializes
+        Err(error) => error_json(error),
+    }
+}
+
+///read every game out of the c strings, either bare placements or a game with its metadata
+///
+///a board that doesn't match the dimensions it was sent with is an error
+fn parse_games(arr: *mut *mut c_char, size: usize) -> Result<Vec<GameInput>, BoardSizeError> {
     let slice = unsafe { std::slice::from_raw_parts(arr, size) };
-    for ptr in slice {
-        let c_str = unsafe { CStr::from_ptr(*ptr) };
-        let rust_string = c_str.to_string_lossy();
-        let placements: Vec<PlacementStats> = serde_json::from_str(&rust_string).unwrap(); //something went wrong in the response loop, error should never happen
-        cumulative_stats.absorb(CumulativePlacementStats::from(placements.as_slice()));
-        //join all handles and their respective stats
+    slice
+        .iter()
+        .map(|ptr| {
+            let c_str = unsafe { CStr::from_ptr(*ptr) };
+            let rust_string = c_str.to_string_lossy();
+            let game: GameInput = envelope::read_versioned(&rust_string).unwrap(); //something went wrong in the response loop, error should never happen
+            game.validate()?;
+            Ok(game)
+        })
+        .collect()
+}
 
-        //  println!("{}", rust_string);
+fn read_bytes<'a>(data: *const u8, len: usize) -> &'a [u8] {
+    unsafe { std::slice::from_raw_parts(data, len) }
+}
+
+///stats over every game, ratios from fewer than `min_samples` samples are left out
+pub fn analyze_games(games: &[GameInput], min_samples: usize) -> PlayerStats {
+    let mut cumulative_stats = CumulativePlacementStats::default();
+    for game in games {
+        cumulative_stats.absorb(CumulativePlacementStats::from(game.placements()));
     }
+    PlayerStats::new(&cumulative_stats, min_samples)
+}
 
-    let stats = PlayerStats::from(&cumulative_stats);
+///stats over every game as bare json, without the envelope `analyze_versioned` adds
+///
+///kept bare for callers from before versioning, the data of the envelope is the same so `output_schema`
+///describes it too. like every function taking games, a board that doesn't match its `boardWidth`
+///and `boardHeight` gives `{"error": ...}` instead
+#[no_mangle]
+pub extern "C" fn analyze(arr: *mut *mut c_char, size: usize) -> *const libc::c_char {
+    let stats = parse_games(arr, size).map(|games| {
+        let mut cumulative_stats = CumulativePlacementStats::default();
+        for game in games {
+            cumulative_stats.absorb(CumulativePlacementStats::from(game.placements()));
+            //join all handles and their respective stats
+        }
+        PlayerStats::from(&cumulative_stats)
+    });
 
-    let result_json = serde_json::to_string(&stats).unwrap();
+    let result_json = result_json(stats);
     std::ffi::CString::new(result_json).unwrap().into_raw()
     /*
     let c_json = unsafe { std::ffi::CStr::from_ptr(json) };
@@ -47,6 +128,340 @@ pub extern "C" fn analyze(arr: *mut *mut c_char, size: usize) -> *const libc::c_
     }*/
 }
 
+///like `analyze` but ratios from fewer than `min_samples` samples are left out of the result
+#[no_mangle]
+pub extern "C" fn analyze_with_min_samples(
+    arr: *mut *mut c_char,
+    size: usize,
+    min_samples: usize,
+) -> *const libc::c_char {
+    let stats = parse_games(arr, size).map(|games| analyze_games(&games, min_samples));
+
+    let result_json = result_json(stats);
+    std::ffi::CString::new(result_json).unwrap().into_raw()
+}
+
+///like `analyze_with_min_samples` but the stats are wrapped in an envelope with the format version
+#[no_mangle]
+pub extern "C" fn analyze_versioned(
+    arr: *mut *mut c_char,
+    size: usize,
+    min_samples: usize,
+) -> *const libc::c_char {
+    let stats = parse_games(arr, size)
+        .map(|games| envelope::Envelope::new(analyze_games(&games, min_samples)));
+
+    let result_json = result_json(stats);
+    std::ffi::CString::new(result_json).unwrap().into_raw()
+}
+
+///json schema of the enveloped input when `output` is false, or of the `analyze_versioned` output
+#[no_mangle]
+pub extern "C" fn json_schema(output: bool) -> *const libc::c_char {
+    let schema = if output {
+        envelope::output_schema()
+    } else {
+        envelope::input_schema()
+    };
+
+    let result_json = serde_json::to_string(&schema).unwrap();
+    std::ffi::CString::new(result_json).unwrap().into_raw()
+}
+
+///compares two outputs of `analyze`, enveloped or not, and flags the significant changes
+///
+///a string that isn't the output of `analyze` gives `{"error": ...}` instead
+#[no_mangle]
+pub extern "C" fn diff_stats(before: *const c_char, after: *const c_char) -> *const libc::c_char {
+    let [before, after] =
+        [before, after].map(|ptr| stats_diff::read_player_stats(&read_c_str(ptr)));
+    let diff =
+        before.and_then(|before| Ok(envelope::Envelope::new(stats_diff::diff(&before, &after?))));
+
+    let result_json = result_json(diff);
+    std::ffi::CString::new(result_json).unwrap().into_raw()
+}
+
+///like `analyze` but splits the stats by result, opponent and round using each game's metadata
+#[no_mangle]
+pub extern "C" fn analyze_matches(arr: *mut *mut c_char, size: usize) -> *const libc::c_char {
+    let report = parse_games(arr, size).map(|games| MatchReport::from(games.as_slice()));
+
+    let result_json = result_json(report);
+    std::ffi::CString::new(result_json).unwrap().into_raw()
+}
+
+///stats of every game over its course, a point per placement over the rolling window of the last `window`
+///placements or seconds
+#[no_mangle]
+pub extern "C" fn analyze_time_series(
+    arr: *mut *mut c_char,
+    size: usize,
+    window: f64,
+    per_second: bool,
+) -> *const libc::c_char {
+    let window = if per_second {
+        Window::Seconds(window)
+    } else {
+        Window::Placements(window as usize)
+    };
+    let series = parse_games(arr, size).map(|games| {
+        games
+            .iter()
+            .map(|game| GameTimeSeries {
+                game_id: game.meta().map(|meta| meta.game_id.clone()),
+                points: time_series(&CumulativePlacementStats::from(game.placements()), window),
+            })
+            .collect::<Vec<_>>()
+    });
+
+    let result_json = result_json(series);
+    std::ffi::CString::new(result_json).unwrap().into_raw()
+}
+
+///turns a tetr.io `.ttr` or `.ttrm` replay into games that `analyze` and `analyze_matches` accept
+///
+///a file that isn't a tetr.io replay gives `{"error": ...}` instead of the games
+#[no_mangle]
+pub extern "C" fn import_tetrio_replay(replay: *const c_char) -> *const libc::c_char {
+    let result_json = result_json(tetrio::import_tetrio_replay(&read_c_str(replay)));
+    std::ffi::CString::new(result_json).unwrap().into_raw()
+}
+
+///turns a decompressed jstris replay into a game that `analyze` and `analyze_matches` accept
+///
+///jstris hands out lz-string compressed replays, callers decompress them to json before passing them in. a
+///string that isn't a jstris replay, compressed ones included, gives `{"error": ...}` instead of the game
+#[no_mangle]
+pub extern "C" fn import_jstris_replay(replay: *const c_char) -> *const libc::c_char {
+    let result_json = result_json(jstris::import_jstris_replay(&read_c_str(replay)));
+    std::ffi::CString::new(result_json).unwrap().into_raw()
+}
+
+///compares two players from the same round, each string is one side's game
+#[no_mangle]
+pub extern "C" fn analyze_versus(
+    player: *const c_char,
+    opponent: *const c_char,
+) -> *const libc::c_char {
+    let [player, opponent] = [player, opponent].map(|ptr| {
+        let game: GameInput = envelope::read_versioned(&read_c_str(ptr)).unwrap(); //something went wrong in the response loop, error should never happen
+        game.validate().map(|_| game)
+    });
+    let report = player.and_then(|player| Ok(versus::analyze_versus(&player, &opponent?)));
+
+    let result_json = result_json(report);
+    std::ffi::CString::new(result_json).unwrap().into_raw()
+}
+
+///like `analyze` but the games come packed in the binary format
+///
+///bytes that aren't in the binary format give `{"error": ...}` instead of the stats
+#[no_mangle]
+pub extern "C" fn analyze_binary(data: *const u8, len: usize) -> *const libc::c_char {
+    let stats = binary::decode_games(read_bytes(data, len)).map(|games| analyze_games(&games, 0));
+
+    let result_json = result_json(stats);
+    std::ffi::CString::new(result_json).unwrap().into_raw()
+}
+
+///packs a json array of games into the binary format, the length is written to `out_len`
+///
+///the bytes have to be handed back to `free_binary`. json that isn't a list of games gives a null pointer with
+///`{"error": ...}` written to `out_error`, which is null otherwise
+#[no_mangle]
+pub extern "C" fn json_to_binary(
+    json: *const c_char,
+    out_len: &mut usize,
+    out_error: &mut *const c_char,
+) -> *mut u8 {
+    match binary::json_to_binary(&read_c_str(json)) {
+        Ok(bytes) => {
+            *out_len = bytes.len();
+            *out_error = std::ptr::null();
+            Box::into_raw(bytes.into_boxed_slice()) as *mut u8
+        }
+        Err(error) => {
+            *out_len = 0;
+            *out_error = std::ffi::CString::new(error_json(error))
+                .unwrap()
+                .into_raw();
+            std::ptr::null_mut()
+        }
+    }
+}
+
+///unpacks the binary format into a json array of games, bytes that aren't in the format give `{"error": ...}`
+#[no_mangle]
+pub extern "C" fn binary_to_json(data: *const u8, len: usize) -> *const libc::c_char {
+    let result_json = binary::binary_to_json(read_bytes(data, len)).unwrap_or_else(error_json);
+    std::ffi::CString::new(result_json).unwrap().into_raw()
+}
+
+#[no_mangle]
+pub extern "C" fn free_binary(data: *mut u8, len: usize) {
+    let bytes = std::ptr::slice_from_raw_parts_mut(data, len);
+    drop(unsafe { Box::from_raw(bytes) });
+}
+
+///a csv row for every placement of every game, see `export::PlacementRow` for the columns
+///
+///games that can't be read give `{"error": ...}` instead of the csv
+#[no_mangle]
+pub extern "C" fn export_placements(arr: *mut *mut c_char, size: usize) -> *const libc::c_char {
+    let csv = match parse_games(arr, size) {
+        Ok(games) => {
+            let mut csv = Vec::new();
+            let rows = export::placement_rows(&games);
+            export::write_csv(&rows, &mut csv).unwrap(); //writing to memory can't fail
+            csv
+        }
+        Err(error) => error_json(error).into_bytes(),
+    };
+
+    std::ffi::CString::new(csv).unwrap().into_raw()
+}
+
+///like `export_placements` but as a parquet file, the length is written to `out_len`
+///
+///the bytes have to be handed back to `free_binary`. games that can't be read give a null pointer
+///with `{"error": ...}` written to `out_error`, which is null otherwise
+#[cfg(feature = "parquet")]
+#[no_mangle]
+pub extern "C" fn export_placements_parquet(
+    arr: *mut *mut c_char,
+    size: usize,
+    out_len: &mut usize,
+    out_error: &mut *const c_char,
+) -> *mut u8 {
+    let games = match parse_games(arr, size) {
+        Ok(games) => games,
+        Err(error) => {
+            *out_len = 0;
+            *out_error = std::ffi::CString::new(error_json(error))
+                .unwrap()
+                .into_raw();
+            return std::ptr::null_mut();
+        }
+    };
+    let rows = export::placement_rows(&games);
+    let mut bytes = Vec::new();
+    export::write_parquet(&rows, &mut bytes).unwrap(); //writing to memory can't fail
+    *out_len = bytes.len();
+    *out_error = std::ptr::null();
+    Box::into_raw(bytes.into_boxed_slice()) as *mut u8
+}
+
+///every placement of every game with its values and tags like missed spikes or slow placements
+#[no_mangle]
+pub extern "C" fn review_games(arr: *mut *mut c_char, size: usize) -> *const libc::c_char {
+    let reviews =
+        parse_games(arr, size).map(|games| envelope::Envelope::new(review::review_games(&games)));
+
+    let result_json = result_json(reviews);
+    std::ffi::CString::new(result_json).unwrap().into_raw()
+}
+
+///the `count` biggest spikes, hesitations, missed attacks and defence collapses of every game
+#[no_mangle]
+pub extern "C" fn key_moments(
+    arr: *mut *mut c_char,
+    size: usize,
+    count: usize,
+) -> *const libc::c_char {
+    let moments = parse_games(arr, size)
+        .map(|games| envelope::Envelope::new(key_moments::key_moments_games(&games, count)));
+
+    let result_json = result_json(moments);
+    std::ffi::CString::new(result_json).unwrap().into_raw()
+}
+
+#[derive(Serialize)]
+struct FumenSolution {
+    attack: usize,
+    defence: usize,
+}
+
+fn fumen_solution(
+    fumen: &str,
+    queue: &str,
+    btb: usize,
+    combo: usize,
+) -> Result<FumenSolution, PositionError> {
+    let (board, width) = parse_board(fumen, fumen::FUMEN_WIDTH)?;
+    let queue = parse_queue(queue)?;
+    let board = BitBoard::new(&board, width);
+    //fumen boards always fit the solver, so only a missing queue leaves nothing to solve
+    let (attack, defence) = solver::solve_state(&board, btb, combo, &queue)
+        .ok_or_else(|| PositionError("no pieces to solve with".to_string()))?;
+    Ok(FumenSolution { attack, defence })
+}
+
+///solves the board on the first page of a fumen, `queue` is piece letters or a json array starting with the hold
+///
+///reads its input like `analyze_position`, which also takes fumen and gives the solution along with the rest. a
+///string that isn't a fumen, a queue that isn't pieces or an empty queue gives `{"error": ...}` instead
+#[no_mangle]
+pub extern "C" fn solve_fumen(
+    fumen: *const c_char,
+    queue: *const c_char,
+    btb: usize,
+    combo: usize,
+) -> *const libc::c_char {
+    let solution = fumen_solution(&read_c_str(fumen), &read_c_str(queue), btb, combo);
+
+    let result_json = result_json(solution);
+    std::ffi::CString::new(result_json).unwrap().into_raw()
+}
+
+fn position_analysis(
+    board: &str,
+    width: usize,
+    hold: &str,
+    queue: &str,
+    btb: usize,
+    combo: usize,
+) -> Result<position::PositionAnalysis, PositionError> {
+    let (board, width) = parse_board(board, width)?;
+    let hold = parse_queue(hold)?;
+    let queue = parse_queue(queue)?;
+    Ok(position::analyze_position(
+        &board,
+        width,
+        hold.first().copied().unwrap_or(MinoType::Empty),
+        &queue,
+        btb,
+        combo,
+    ))
+}
+
+///analyzes a single board without a game around it
+///
+///`board` is a json array of pieces `width` wide or a fumen, `hold` and `queue` are piece letters or json arrays
+///and `queue` starts with the current piece. a board, hold or queue that can't be read gives `{"error": ...}`
+///instead
+#[no_mangle]
+pub extern "C" fn analyze_position(
+    board: *const c_char,
+    width: usize,
+    hold: *const c_char,
+    queue: *const c_char,
+    btb: usize,
+    combo: usize,
+) -> *const libc::c_char {
+    let analysis = position_analysis(
+        &read_c_str(board),
+        width,
+        &read_c_str(hold),
+        &read_c_str(queue),
+        btb,
+        combo,
+    );
+
+    let result_json = result_json(analysis);
+    std::ffi::CString::new(result_json).unwrap().into_raw()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -56,4 +471,62 @@ mod tests {
         let result = add(2, 2);
         assert_eq!(result, 4);
     }
+
+    #[test]
+    fn unsolvable_fumen_input_is_an_error_object() {
+        let error = |fumen: &str, queue: &str| {
+            let json: serde_json::Value =
+                serde_json::from_str(&result_json(fumen_solution(fumen, queue, 0, 0))).unwrap();
+            json["error"].as_str().map(str::to_string)
+        };
+        assert!(error("v115@vhAAgH", "").is_some());
+        assert!(error("v115@vhAAgH", "TX").is_some());
+        assert!(error("not a fumen", "T").is_some());
+    }
+
+    #[test]
+    fn boards_that_dont_match_their_size_are_an_error_object() {
+        let mut placement = test_support::placement();
+        placement.board_width = 12;
+        let game = CString::new(serde_json::to_string(&[placement]).unwrap()).unwrap();
+        let mut games = [game.as_ptr() as *mut c_char];
+        let json = analyze(games.as_mut_ptr(), games.len());
+        let json = unsafe { CString::from_raw(json as *mut c_char) };
+        let json: serde_json::Value = serde_json::from_str(json.to_str().unwrap()).unwrap();
+        assert!(json["error"].is_string());
+    }
+
+    #[test]
+    fn corrupt_binary_is_an_error_instead_of_a_panic() {
+        let corrupt = b"not binary";
+        for json in [
+            binary_to_json(corrupt.as_ptr(), corrupt.len()),
+            analyze_binary(corrupt.as_ptr(), corrupt.len()),
+        ] {
+            let json = unsafe { CString::from_raw(json as *mut c_char) };
+            let json: serde_json::Value = serde_json::from_str(json.to_str().unwrap()).unwrap();
+            assert!(json["error"].is_string());
+        }
+
+        let games = CString::new("not games").unwrap();
+        let (mut len, mut error) = (1, std::ptr::null());
+        let bytes = json_to_binary(games.as_ptr(), &mut len, &mut error);
+        assert!(bytes.is_null());
+        assert_eq!(len, 0);
+        let error = unsafe { CString::from_raw(error as *mut c_char) };
+        assert!(error.to_str().unwrap().contains("error"));
+    }
+
+    #[test]
+    fn unreadable_positions_are_an_error_object() {
+        let error = |board: &str, width: usize| {
+            let analysis = position_analysis(board, width, "T", "IO", 0, 0);
+            let json: serde_json::Value = serde_json::from_str(&result_json(analysis)).unwrap();
+            json["error"].as_str().map(str::to_string)
+        };
+        let wide = serde_json::to_string(&vec![MinoType::Empty; 100 * 4]).unwrap();
+        assert!(error(&wide, 100).is_some());
+        assert!(error("[0, 8, 8]", 2).is_some());
+        assert!(error("not a fumen", 10).is_some());
+    }
 }
@@ -0,0 +1,202 @@
+use crate::placement_stats::CumulativePlacementStats;
+use crate::player_stats::PlayerStats;
+use crate::replay_response::{BoardSizeError, PlacementStats};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+///where a game came from, every field but the id is optional so partial exports still work
+#[derive(Debug, Default, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GameMeta {
+    pub game_id: String,
+    #[serde(default)]
+    pub match_id: Option<String>,
+    #[serde(default)]
+    pub round: Option<usize>,
+    #[serde(default)]
+    pub opponent_id: Option<String>,
+    #[serde(default)]
+    pub mode: Option<String>,
+    #[serde(default)]
+    pub won: Option<bool>,
+    ///iso 8601 timestamp of when the game started
+    #[serde(default)]
+    pub start_time: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Game {
+    #[serde(flatten)]
+    pub meta: GameMeta,
+    pub placements: Vec<PlacementStats>,
+}
+
+///a game with its metadata, or a bare list of placements like older producers send
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum GameInput {
+    Game(Game),
+    Placements(Vec<PlacementStats>),
+}
+
+impl GameInput {
+    pub fn meta(&self) -> Option<&GameMeta> {
+        match self {
+            GameInput::Game(game) => Some(&game.meta),
+            GameInput::Placements(_) => None,
+        }
+    }
+
+    pub fn placements(&self) -> &[PlacementStats] {
+        match self {
+            GameInput::Game(game) => &game.placements,
+            GameInput::Placements(placements) => placements,
+        }
+    }
+
+    ///make sure every board has as many cells as its dimensions say
+    pub fn validate(&self) -> Result<(), BoardSizeError> {
+        self.placements()
+            .iter()
+            .try_for_each(PlacementStats::validate)
+    }
+}
+
+///the record against a single opponent
+#[derive(Serialize, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct HeadToHead {
+    pub games: usize,
+    pub wins: usize,
+    pub losses: usize,
+    ///none until a game against this opponent has a known result
+    pub win_rate: Option<f64>,
+    ///stats of the player against this opponent minus their overall stats
+    pub apm_difference: Option<f64>,
+    pub pps_difference: Option<f64>,
+    pub vs_difference: Option<f64>,
+}
+
+#[derive(Serialize, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchReport {
+    pub games: usize,
+    pub overall: PlayerStats,
+    pub wins: PlayerStats,
+    pub losses: PlayerStats,
+    pub by_opponent: HashMap<String, PlayerStats>,
+    pub by_round: HashMap<usize, PlayerStats>,
+    pub head_to_head: HashMap<String, HeadToHead>,
+}
+
+impl From<&[GameInput]> for MatchReport {
+    fn from(games: &[GameInput]) -> Self {
+        let mut overall = CumulativePlacementStats::default();
+        let mut wins = CumulativePlacementStats::default();
+        let mut losses = CumulativePlacementStats::default();
+        let mut by_opponent: HashMap<String, CumulativePlacementStats> = HashMap::new();
+        let mut by_round: HashMap<usize, CumulativePlacementStats> = HashMap::new();
+        let mut head_to_head: HashMap<String, HeadToHead> = HashMap::new();
+
+        for game in games {
+            let stats = CumulativePlacementStats::from(game.placements());
+            if let Some(meta) = game.meta() {
+                match meta.won {
+                    Some(true) => wins.absorb_ref(&stats),
+                    Some(false) => losses.absorb_ref(&stats),
+                    None => {}
+                }
+                if let Some(opponent) = &meta.opponent_id {
+                    by_opponent
+                        .entry(opponent.clone())
+                        .or_default()
+                        .absorb_ref(&stats);
+                    let record = head_to_head.entry(opponent.clone()).or_default();
+                    record.games += 1;
+                    match meta.won {
+                        Some(true) => record.wins += 1,
+                        Some(false) => record.losses += 1,
+                        None => {}
+                    }
+                }
+                if let Some(round) = meta.round {
+                    by_round.entry(round).or_default().absorb_ref(&stats);
+                }
+            }
+            overall.absorb(stats);
+        }
+
+        let overall = PlayerStats::from(&overall);
+        let by_opponent: HashMap<_, _> = by_opponent
+            .iter()
+            .map(|(opponent, stats)| (opponent.clone(), PlayerStats::from(stats)))
+            .collect();
+        for (opponent, record) in head_to_head.iter_mut() {
+            let stats = &by_opponent[opponent];
+            let decided = record.wins + record.losses;
+            record.win_rate = (decided > 0).then(|| record.wins as f64 / decided as f64);
+            let difference = |stats: Option<f64>, overall: Option<f64>| {
+                stats.zip(overall).map(|(stats, overall)| stats - overall)
+            };
+            record.apm_difference = difference(stats.apm, overall.apm);
+            record.pps_difference = difference(stats.pps, overall.pps);
+            record.vs_difference = difference(stats.vs, overall.vs);
+        }
+
+        Self {
+            games: games.len(),
+            overall,
+            wins: PlayerStats::from(&wins),
+            losses: PlayerStats::from(&losses),
+            by_opponent,
+            by_round: by_round
+                .iter()
+                .map(|(&round, stats)| (round, PlayerStats::from(stats)))
+                .collect(),
+            head_to_head,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::placement;
+
+    fn game(opponent: &str, won: Option<bool>) -> GameInput {
+        GameInput::Game(Game {
+            meta: GameMeta {
+                game_id: "game".to_string(),
+                opponent_id: Some(opponent.to_string()),
+                won,
+                ..Default::default()
+            },
+            placements: (0..10).map(|_| placement()).collect(),
+        })
+    }
+
+    #[test]
+    fn win_rate_only_counts_games_with_a_result() {
+        let games = [
+            game("a", Some(true)),
+            game("a", Some(false)),
+            game("a", Some(true)),
+            game("a", None),
+        ];
+        let report = MatchReport::from(&games[..]);
+        let record = &report.head_to_head["a"];
+        assert_eq!(record.games, 4);
+        assert_eq!(record.win_rate, Some(2.0 / 3.0));
+    }
+
+    #[test]
+    fn win_rate_is_none_without_any_results() {
+        let games = [game("a", None), game("a", None)];
+        let report = MatchReport::from(&games[..]);
+        let record = &report.head_to_head["a"];
+        assert_eq!(record.win_rate, None);
+        let json = serde_json::to_value(record).unwrap(); //plain struct
+        assert!(json["winRate"].is_null());
+    }
+}
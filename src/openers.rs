@@ -0,0 +1,218 @@
+use crate::bitboard::BitBoard;
+use crate::board_analyzer::{get_garbage_height, get_height, matches_pattern};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+///amount of boards at the start of a game that are checked for an opener
+pub const OPENER_BOARDS: usize = 14;
+
+#[derive(Hash, Debug, Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+pub enum Opener {
+    #[serde(rename = "TKI")]
+    Tki,
+    #[serde(rename = "DT_CANNON")]
+    DtCannon,
+    #[serde(rename = "PCO")]
+    Pco,
+    #[serde(rename = "MKO")]
+    Mko,
+    #[serde(rename = "ALBATROSS")]
+    Albatross,
+    #[serde(rename = "HACHISPIN")]
+    Hachispin,
+}
+
+struct OpenerPattern {
+    opener: Opener,
+    ///shapes the board goes through while building the opener, the last one is the finished setup
+    ///
+    ///the first stage only counts when nothing sits above it, otherwise a few cells of any stack would start an
+    ///opener
+    stages: &'static [&'static [&'static str]],
+}
+
+///how often an opener was used and what it achieved
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct OpenerRecord {
+    pub games: usize,
+    pub completed: usize,
+    pub attack: usize,
+    pub frames: f64,
+}
+
+//left handed versions, mirrored boards are matched as well
+#[rustfmt::skip]
+const OPENERS: [OpenerPattern; 6] = [
+    OpenerPattern {
+        opener: Opener::Tki,
+        stages: &[
+            &[
+                "#...??####",
+                "##.???####",
+            ],
+            &[
+                "##????????",
+                "#...######",
+                "##.#######",
+            ],
+        ],
+    },
+    OpenerPattern {
+        opener: Opener::DtCannon,
+        stages: &[
+            &[
+                "##.???####",
+                "##.???####",
+                "#..#######",
+            ],
+            &[
+                "##????????",
+                "#...######",
+                "##.#######",
+                "##.#######",
+                "#..#######",
+            ],
+        ],
+    },
+    OpenerPattern {
+        opener: Opener::Pco,
+        stages: &[
+            &[
+                "###...####",
+                "###..#####",
+            ],
+            &[
+                "#......###",
+                "##.....###",
+                "###...####",
+                "###..#####",
+            ],
+        ],
+    },
+    OpenerPattern {
+        opener: Opener::Mko,
+        stages: &[
+            &[
+                "######...?",
+                "#######.??",
+            ],
+            &[
+                "??????##??",
+                "######...#",
+                "#######.##",
+            ],
+        ],
+    },
+    OpenerPattern {
+        opener: Opener::Albatross,
+        stages: &[
+            &[
+                "??###.####",
+                "####..####",
+            ],
+            &[
+                "???##?????",
+                "???#...###",
+                "#####.####",
+                "####..####",
+            ],
+        ],
+    },
+    OpenerPattern {
+        opener: Opener::Hachispin,
+        stages: &[
+            &[
+                "#..???####",
+                "##.???####",
+                "#..#######",
+            ],
+            &[
+                "#.........",
+                "#..#######",
+                "##.#######",
+                "#..#######",
+            ],
+        ],
+    },
+];
+
+///finds the opener that was built furthest in the boards at the start of a game
+///
+///returns the opener and whether its finished setup was reached, openers only exist on standard width boards
+pub fn recognize_opener<'a>(
+    boards: impl IntoIterator<Item = &'a BitBoard>,
+) -> Option<(Opener, bool)> {
+    let mut progress = [None; OPENERS.len()];
+    for board in boards
+        .into_iter()
+        .filter(|board| board.width() == 10)
+        .take(OPENER_BOARDS)
+    {
+        let garbage_height = get_garbage_height(board);
+        let height = get_height(board);
+        for (pattern, stage) in OPENERS.iter().zip(progress.iter_mut()) {
+            for (i, shape) in pattern.stages.iter().enumerate().rev() {
+                if stage.is_some_and(|s| s >= i) {
+                    break;
+                }
+                if i == 0 && height > garbage_height + shape.len() {
+                    continue;
+                }
+                if matches_pattern(board, shape, 0, garbage_height, false)
+                    || matches_pattern(board, shape, 0, garbage_height, true)
+                {
+                    *stage = Some(i);
+                    break;
+                }
+            }
+        }
+    }
+    OPENERS
+        .iter()
+        .zip(progress)
+        .filter_map(|(pattern, stage)| stage.map(|stage| (pattern, stage)))
+        //earlier openers win ties
+        .rev()
+        .max_by(|(a, a_stage), (b, b_stage)| {
+            let a_progress = (a_stage + 1) as f64 / a.stages.len() as f64;
+            let b_progress = (b_stage + 1) as f64 / b.stages.len() as f64;
+            a_progress.total_cmp(&b_progress)
+        })
+        .map(|(pattern, stage)| (pattern.opener, stage == pattern.stages.len() - 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::board;
+
+    fn opener(rows: &[&str]) -> Option<(Opener, bool)> {
+        recognize_opener([&BitBoard::new(&board(rows), 10)])
+    }
+
+    #[test]
+    fn openers_are_recognized_while_building_and_finished() {
+        assert_eq!(
+            opener(&["T...LLZZOO", "SS.JLZZIOO"]),
+            Some((Opener::Tki, false))
+        );
+        let pco = ["I......LLL", "II.....LSS", "IJJ...SSOO", "IJJ..ZZOOO"];
+        assert_eq!(opener(&pco), Some((Opener::Pco, true)));
+        //left handed
+        assert_eq!(
+            opener(&["OOZZLL...T", "OOIZZJS.SS"]),
+            Some((Opener::Tki, false))
+        );
+    }
+
+    #[test]
+    fn flat_stacks_are_not_openers() {
+        assert_eq!(opener(&["LL.OOSSZZI", "LL.OOSSZZI"]), None);
+        assert_eq!(opener(&["..........", "LLLJJJOOI."]), None);
+    }
+
+    #[test]
+    fn first_stages_with_cells_above_them_are_not_openers() {
+        assert_eq!(opener(&["T.........", "LLL...JJJJ", "LLL..IIIIO"]), None);
+    }
+}
@@ -0,0 +1,205 @@
+use crate::bitboard::BitBoard;
+use crate::replay_response::MinoType;
+use crate::solver::{mino_to_shape, spawn_position, SolverMove};
+use std::collections::{HashSet, VecDeque};
+
+use bitris::prelude::*;
+
+///highest perfect clear we bother searching for
+const MAX_PC_HEIGHT: usize = 4;
+///hold plus the next pieces that can be used for a perfect clear
+const MAX_PC_PIECES: usize = 11;
+///states searched before giving up, the search runs on every low board so it has to stay cheap
+const MAX_PC_NODES: usize = 20_000;
+
+type PcKey = (u64, Option<Shape>, usize);
+
+///states already known to fail and how many more can be visited
+struct PcSearch {
+    failed: HashSet<PcKey>,
+    budget: usize,
+}
+
+///search for a perfect clear on the board with the queue, the first piece in the queue is the hold
+///
+///only standard width boards are searched since the walls of narrower boards are never cleared, and a search
+///that visits more than `MAX_PC_NODES` states gives up as if there was no perfect clear
+pub fn find_pc(board: &BitBoard, queue: &[MinoType]) -> Option<Vec<SolverMove>> {
+    find_pc_within(board, queue, MAX_PC_NODES)
+}
+
+fn find_pc_within(board: &BitBoard, queue: &[MinoType], budget: usize) -> Option<Vec<SolverMove>> {
+    if board.width() != 10 {
+        return None;
+    }
+    let board64 = board.to_board64()?;
+    let spawn = spawn_position(board.width(), board.height());
+    if board64.is_empty() || occupied_above(&board64, MAX_PC_HEIGHT) {
+        return None;
+    }
+    let mut shapes: VecDeque<_> = queue
+        .iter()
+        .filter_map(|&p| mino_to_shape(p))
+        .take(MAX_PC_PIECES)
+        .collect();
+    let hold = shapes.pop_front()?;
+
+    let filled = (0..MAX_PC_HEIGHT)
+        .flat_map(|y| (0..10).map(move |x| Location { x, y: y as i32 }))
+        .filter(|&location| board64.is_occupied_at(location))
+        .count();
+    let height = (1..=MAX_PC_HEIGHT)
+        .find(|&lines| !occupied_above(&board64, lines))
+        .unwrap_or(MAX_PC_HEIGHT);
+
+    let mut search = PcSearch {
+        failed: HashSet::new(),
+        budget,
+    };
+    for lines in height..=MAX_PC_HEIGHT {
+        let empty = lines * 10 - filled;
+        if empty % 4 != 0 {
+            continue;
+        }
+        //the hold piece can be used as well
+        if empty / 4 > shapes.len() + 1 {
+            break;
+        }
+        let mut moves = Vec::new();
+        //failed states depend on how many lines are being cleared
+        search.failed.clear();
+        if pc_dfs(
+            board64,
+            spawn,
+            lines,
+            Some(hold),
+            &mut shapes,
+            &mut moves,
+            &mut search,
+        ) {
+            return Some(moves);
+        }
+    }
+    None
+}
+
+fn occupied_above(board: &Board64, lines: usize) -> bool {
+    //pieces are at most 4 tall, nothing can float higher than that
+    (lines..lines + 4).any(|y| (0..10).any(|x| board.is_occupied_at(Location { x, y: y as i32 })))
+}
+
+///packed representation of the rows a perfect clear can touch, used to skip revisiting states
+fn pc_key(board: &Board64, hold: Option<Shape>, remaining: usize) -> PcKey {
+    let mut key = 0;
+    for y in 0..MAX_PC_HEIGHT + 2 {
+        for x in 0..10 {
+            key <<= 1;
+            if board.is_occupied_at(Location { x, y: y as i32 }) {
+                key |= 1;
+            }
+        }
+    }
+    (key, hold, remaining)
+}
+
+fn pc_dfs(
+    board: Board64,
+    spawn: CcPosition,
+    lines: usize,
+    hold: Option<Shape>,
+    queue: &mut VecDeque<Shape>,
+    moves: &mut Vec<SolverMove>,
+    search: &mut PcSearch,
+) -> bool {
+    if board.is_empty() {
+        return true;
+    }
+    if search.budget == 0 {
+        return false;
+    }
+    search.budget -= 1;
+    let key = pc_key(&board, hold, queue.len());
+    if search.failed.contains(&key) {
+        return false;
+    }
+
+    let current = queue.pop_front();
+    //place the current piece and keep hold, or swap it with the hold piece
+    let mut options = Vec::new();
+    if let Some(current) = current {
+        options.push((current, hold));
+    }
+    if let Some(held) = hold.filter(|&held| Some(held) != current) {
+        options.push((held, current));
+    }
+    let mut found = false;
+    'search: for (shape, next_hold) in options {
+        let spawn_placement = Piece::new(shape, Orientation::North)
+            .with(spawn)
+            .to_bl_placement();
+        if !spawn_placement.is_in_free_space(&board) {
+            continue;
+        }
+        for placement in MoveRules::default().generate_minimized_moves(board, spawn_placement) {
+            let mut new_board = board;
+            let lines_cleared = placement
+                .place_on_and_clear_lines(&mut new_board)
+                .unwrap_or(Lines::blank())
+                .count() as usize;
+            let lines_left = lines - lines_cleared.min(lines);
+            if occupied_above(&new_board, lines_left) {
+                continue;
+            }
+            moves.push(SolverMove::from(&placement));
+            if pc_dfs(
+                new_board, spawn, lines_left, next_hold, queue, moves, search,
+            ) {
+                found = true;
+                break 'search;
+            }
+            moves.pop();
+        }
+    }
+    if let Some(current) = current {
+        queue.push_front(current);
+    }
+    if !found {
+        search.failed.insert(key);
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::position::parse_queue;
+    use crate::test_support::board;
+
+    ///four lines with a 4x4 box open on the right
+    fn open_box() -> BitBoard {
+        BitBoard::new(&board(&["LLLLLL...."; 4]), 10)
+    }
+
+    #[test]
+    fn finds_a_four_line_pc() {
+        //the t can't be part of the pc so it has to be held past
+        let queue = parse_queue("OTOOO").unwrap(); //valid queue
+        let moves = find_pc(&open_box(), &queue).expect("the box fits four o pieces");
+        assert_eq!(moves.len(), 4);
+        assert!(moves.iter().all(|m| m.shape == MinoType::O));
+    }
+
+    #[test]
+    fn no_pc_when_the_pieces_cant_fill_the_board() {
+        //s pieces can't tile a rectangle
+        let queue = parse_queue("SSSSSS").unwrap(); //valid queue
+        assert!(find_pc(&open_box(), &queue).is_none());
+    }
+
+    #[test]
+    fn the_budget_cuts_the_search_off() {
+        let queue = parse_queue("OTOOO").unwrap(); //valid queue
+        assert!(find_pc_within(&open_box(), &queue, 1).is_none());
+        assert!(find_pc_within(&open_box(), &queue, MAX_PC_NODES).is_some());
+    }
+}
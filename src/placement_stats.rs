@@ -1,12 +1,28 @@
-use crate::board_analyzer::{get_garbage_height, get_height, get_well, has_cheese};
+use crate::bitboard::BitBoard;
+use crate::board_analyzer::{
+    get_board_features, get_garbage_height, get_height, get_well, has_cheese, BoardFeatures,
+};
+use crate::fumen;
+use crate::garbage_analyzer::{analyze_garbage, dug_segments, mark_dug, GarbageAnalysis};
+use crate::garbage_ledger::{GarbageChunk, GarbageLedger};
+use crate::openers::{recognize_opener, Opener, OpenerRecord, OPENER_BOARDS};
+use crate::pc_finder::find_pc;
 use crate::replay_response::{ClearType, MinoType, PlacementStats};
-use crate::solver::solve_state;
+use crate::setups::{cashed_setup, detect_setups, SETUP_COUNT};
+use crate::solver::{get_death_risk, solve_state, SolverMove};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::time::UNIX_EPOCH;
+
+///death risk at which a board is considered in danger
+const DANGER_RISK: f64 = 0.75;
+///death risk at which a board is one or two rows from topping out
+const NEAR_DEATH_RISK: f64 = 0.9;
+
 ///stats that represents the sum total of the data from several sequences of placements
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct CumulativePlacementStats {
-    pub well_cols: [usize; 10],
+    pub well_cols: Vec<usize>,
     pub clear_types: [usize; 16],
     pub shape_types: [usize; 9],
     pub garbage_cleared: usize,
@@ -18,23 +34,52 @@ pub struct CumulativePlacementStats {
     pub exclusive_stack_cleared: usize,
     pub attack_with_cheese: usize,
     pub exclusive_cheese_cleared: usize,
+    pub attack_with_clean_garbage: usize,
+    pub exclusive_clean_garbage_cleared: usize,
+    pub attack_with_messy_garbage: usize,
+    pub exclusive_messy_garbage_cleared: usize,
+    pub clean_segments_dug: usize,
+    pub messy_segments_dug: usize,
+    pub dig_pieces: usize,
+    pub dig_lines_cleared: usize,
+    pub dig_garbage_cleared: usize,
+    pub garbage_cancelled: usize,
+    pub garbage_chunks: Vec<GarbageChunk>,
+    pub garbage_messiness: Vec<f64>,
     pub delays: Vec<f64>,
+    pub attacks: Vec<usize>,
+    ///spike potential the solver found after each placement, none when it couldn't search the board
+    pub attack_potentials: Vec<Option<usize>>,
     pub stack_heights: Vec<usize>,
     pub garbage_heights: Vec<usize>,
+    pub board_features: Vec<BoardFeatures>,
     pub btb_segments: Vec<BTBSegment>,
     pub combo_segments: Vec<ComboSegment>,
     pub keypresses: usize,
     pub opener_attack: usize,
     pub opener_frames: f64,
     pub opener_blocks: usize,
+    pub openers: HashMap<Opener, OpenerRecord>,
     pub defense_potentials: Vec<usize>,
     pub blockfish_scores: Vec<usize>,
     pub spikable_boards: usize,
     pub pre_spike_boards: usize,
+    pub death_risks: Vec<f64>,
+    pub danger_frames: f64,
+    pub near_death_recoveries: usize,
+    pub pc_opportunities: usize,
+    pub pc_taken: usize,
+    pub setups_built: [usize; SETUP_COUNT],
+    pub setups_cashed: [usize; SETUP_COUNT],
+    ///everything worked out for each placement, in order
+    pub placement_features: Vec<PlacementFeatures>,
 }
 
 impl CumulativePlacementStats {
     fn add_stats(&mut self, stats: &CumulativePlacementStats) {
+        if self.well_cols.len() < stats.well_cols.len() {
+            self.well_cols.resize(stats.well_cols.len(), 0);
+        }
         self.well_cols
             .iter_mut()
             .zip(stats.well_cols.iter())
@@ -47,6 +92,14 @@ impl CumulativePlacementStats {
             .iter_mut()
             .zip(stats.shape_types.iter())
             .for_each(|(c, s)| *c += s);
+        self.setups_built
+            .iter_mut()
+            .zip(stats.setups_built.iter())
+            .for_each(|(c, s)| *c += s);
+        self.setups_cashed
+            .iter_mut()
+            .zip(stats.setups_cashed.iter())
+            .for_each(|(c, s)| *c += s);
         self.garbage_cleared += stats.garbage_cleared;
         self.lines_cleared += stats.lines_cleared;
         self.attack += stats.attack;
@@ -56,27 +109,57 @@ impl CumulativePlacementStats {
         self.exclusive_stack_cleared += stats.exclusive_stack_cleared;
         self.attack_with_cheese += stats.attack_with_cheese;
         self.exclusive_cheese_cleared += stats.exclusive_cheese_cleared;
+        self.attack_with_clean_garbage += stats.attack_with_clean_garbage;
+        self.exclusive_clean_garbage_cleared += stats.exclusive_clean_garbage_cleared;
+        self.attack_with_messy_garbage += stats.attack_with_messy_garbage;
+        self.exclusive_messy_garbage_cleared += stats.exclusive_messy_garbage_cleared;
+        self.clean_segments_dug += stats.clean_segments_dug;
+        self.messy_segments_dug += stats.messy_segments_dug;
+        self.dig_pieces += stats.dig_pieces;
+        self.dig_lines_cleared += stats.dig_lines_cleared;
+        self.dig_garbage_cleared += stats.dig_garbage_cleared;
+        self.garbage_cancelled += stats.garbage_cancelled;
 
         self.keypresses += stats.keypresses;
         self.opener_attack += stats.opener_attack;
         self.opener_frames += stats.opener_frames;
         self.opener_blocks += stats.opener_blocks;
+        for (opener, record) in stats.openers.iter() {
+            let total = self.openers.entry(*opener).or_default();
+            total.games += record.games;
+            total.completed += record.completed;
+            total.attack += record.attack;
+            total.frames += record.frames;
+        }
 
         self.spikable_boards += stats.spikable_boards;
         self.pre_spike_boards += stats.pre_spike_boards;
+
+        self.danger_frames += stats.danger_frames;
+        self.near_death_recoveries += stats.near_death_recoveries;
+
+        self.pc_opportunities += stats.pc_opportunities;
+        self.pc_taken += stats.pc_taken;
     }
     ///combine stats while consuming the other
     pub fn absorb(&mut self, stats: CumulativePlacementStats) {
         self.add_stats(&stats);
 
         self.delays.extend(stats.delays);
+        self.attacks.extend(stats.attacks);
+        self.attack_potentials.extend(stats.attack_potentials);
         self.stack_heights.extend(stats.stack_heights);
         self.garbage_heights.extend(stats.garbage_heights);
+        self.garbage_messiness.extend(stats.garbage_messiness);
+        self.garbage_chunks.extend(stats.garbage_chunks);
+        self.board_features.extend(stats.board_features);
         self.btb_segments.extend(stats.btb_segments);
         self.combo_segments.extend(stats.combo_segments);
 
         self.defense_potentials.extend(stats.defense_potentials);
         self.blockfish_scores.extend(stats.blockfish_scores);
+        self.death_risks.extend(stats.death_risks);
+        self.placement_features.extend(stats.placement_features);
     }
     ///combine stats with a reference and cloning
     #[allow(dead_code)]
@@ -84,25 +167,30 @@ impl CumulativePlacementStats {
         self.add_stats(stats);
 
         self.delays.extend(stats.delays.clone());
+        self.attacks.extend(stats.attacks.clone());
+        self.attack_potentials
+            .extend(stats.attack_potentials.clone());
         self.stack_heights.extend(stats.stack_heights.clone());
         self.garbage_heights.extend(stats.garbage_heights.clone());
+        self.garbage_messiness
+            .extend(stats.garbage_messiness.clone());
+        self.garbage_chunks.extend(stats.garbage_chunks.clone());
+        self.board_features.extend(stats.board_features.clone());
         self.btb_segments.extend(stats.btb_segments.clone());
         self.combo_segments.extend(stats.combo_segments.clone());
 
         self.defense_potentials
             .extend(stats.defense_potentials.clone());
         self.blockfish_scores.extend(stats.blockfish_scores.clone());
+        self.death_risks.extend(stats.death_risks.clone());
+        self.placement_features
+            .extend(stats.placement_features.clone());
     }
 }
 
 impl From<&[PlacementStats]> for CumulativePlacementStats {
     fn from(game: &[PlacementStats]) -> Self {
-        let blockfish_config = blockfish::Config {
-            search_limit: 100,
-            parameters: blockfish::Parameters::default(),
-        };
-
-        let mut blockfish = blockfish::ai::AI::new(blockfish_config);
+        let mut blockfish = new_blockfish();
 
         let mut stats = CumulativePlacementStats::default();
         let mut opener_over = false;
@@ -111,6 +199,14 @@ impl From<&[PlacementStats]> for CumulativePlacementStats {
         let mut current_btb = None;
 
         let mut spike_grace_period = 0;
+        let mut near_death = false;
+        //placements covered by the last perfect clear opportunity
+        let mut pc_window_end = 0;
+        let mut previous_setups = Vec::new();
+        let mut previous_board = None;
+        let mut previous_garbage: Option<GarbageAnalysis> = None;
+        let mut garbage_ledger = GarbageLedger::default();
+        let mut opener_boards = Vec::new();
 
         for (i, placement) in game.iter().enumerate() {
             if !opener_over
@@ -122,8 +218,52 @@ impl From<&[PlacementStats]> for CumulativePlacementStats {
                 //log opener over if we skim clear a garbage line
             }
 
+            let width = placement.board_width;
+            let board = BitBoard::new(&placement.board, width);
+            if !opener_over && opener_boards.len() < OPENER_BOARDS {
+                opener_boards.push(board.clone());
+            }
+            if stats.well_cols.len() < width {
+                stats.well_cols.resize(width, 0);
+            }
+
             stats.shape_types[placement.shape as usize] += 1;
-            let height = get_height(&placement.board);
+
+            //setups are judged on the board the t piece was placed on
+            if placement.shape == MinoType::T
+                && (placement.clear_type == ClearType::TspinDouble
+                    || placement.clear_type == ClearType::TspinTriple)
+            {
+                //only the setup the t piece went into gets the credit
+                let cashed = previous_board.as_ref().and_then(|previous| {
+                    let risen = placement.attack_tanked.iter().sum();
+                    cashed_setup(
+                        &previous_setups,
+                        previous,
+                        &board,
+                        placement.lines_cleared,
+                        risen,
+                    )
+                });
+                if let Some(setup) = cashed {
+                    stats.setups_cashed[setup as usize] += 1;
+                }
+            }
+            let setups = detect_setups(&board);
+            //a setup can be found in several places but is only built once
+            let mut kinds: Vec<_> = setups.iter().map(|setup| setup.setup).collect();
+            kinds.dedup();
+            for setup in kinds {
+                if !previous_setups
+                    .iter()
+                    .any(|previous| previous.setup == setup)
+                {
+                    stats.setups_built[setup as usize] += 1;
+                }
+            }
+            previous_setups = setups;
+
+            let height = get_height(&board);
 
             if height == 0 {
                 stats.clear_types[ClearType::PerfectClear as usize] += 1;
@@ -135,6 +275,7 @@ impl From<&[PlacementStats]> for CumulativePlacementStats {
 
             let attack = placement.attack.iter().sum::<usize>();
             stats.attack += attack;
+            stats.attacks.push(attack);
 
             if !opener_over {
                 stats.opener_blocks += 1;
@@ -150,20 +291,55 @@ impl From<&[PlacementStats]> for CumulativePlacementStats {
                 stats.exclusive_stack_cleared += placement.lines_cleared
             }
 
-            let just_ate_cheese =
-                i != 0 && placement.garbage_cleared > 0 && has_cheese(&game[i - 1].board);
+            let just_ate_cheese = i != 0
+                && placement.garbage_cleared > 0
+                && previous_board.as_ref().is_some_and(has_cheese);
             if just_ate_cheese {
                 stats.attack_with_cheese += attack;
                 stats.exclusive_cheese_cleared += placement.lines_cleared;
             }
 
+            let mut garbage = analyze_garbage(&board);
+            if let Some(messiness) = garbage.messiness {
+                stats.garbage_messiness.push(messiness);
+            }
+            garbage.cancelled = garbage_ledger.record(i, placement);
+            garbage.incoming = garbage_ledger.pending();
+            if let Some(previous) = previous_garbage.as_ref().filter(|g| g.rows > 0) {
+                stats.dig_pieces += 1;
+                stats.dig_lines_cleared += placement.lines_cleared;
+                stats.dig_garbage_cleared += placement.garbage_cleared;
+                if placement.garbage_cleared > 0 {
+                    let top_is_clean = previous.segments.last().is_some_and(|s| s.clean);
+                    if top_is_clean {
+                        stats.attack_with_clean_garbage += attack;
+                        stats.exclusive_clean_garbage_cleared += placement.lines_cleared;
+                    } else {
+                        stats.attack_with_messy_garbage += attack;
+                        stats.exclusive_messy_garbage_cleared += placement.lines_cleared;
+                    }
+                    for segment in dug_segments(previous, placement.garbage_cleared) {
+                        if segment.clean {
+                            stats.clean_segments_dug += 1;
+                        } else {
+                            stats.messy_segments_dug += 1;
+                        }
+                    }
+                }
+            }
+            previous_garbage = Some(garbage.clone());
+
             stats.delays.push(round_delay(placement.frame_delay));
             stats.keypresses += placement.keypresses;
 
-            let garbage_height = get_garbage_height(&placement.board);
+            let garbage_height = get_garbage_height(&board);
+            let (well_col, well_height) = get_well(&board);
+            let well = (well_height > 4).then_some(well_col);
+            let mut wellshift = false;
 
             stats.stack_heights.push(height - garbage_height);
             stats.garbage_heights.push(garbage_height);
+            stats.board_features.push(get_board_features(&board));
 
             if placement.lines_cleared > 0 {
                 current_combo = match current_combo {
@@ -202,11 +378,8 @@ impl From<&[PlacementStats]> for CumulativePlacementStats {
                 current_btb = match current_btb {
                     None => {
                         if placement.lines_cleared > 0 {
-                            let mut well = None;
-                            let (col, height) = get_well(&placement.board);
-                            if height > 4 {
+                            if let Some(col) = well {
                                 stats.well_cols[col] += 1;
-                                well = Some(col);
                             }
                             Some(BTBSegment::new(attack, placement.shape, well))
                         } else {
@@ -233,14 +406,12 @@ impl From<&[PlacementStats]> for CumulativePlacementStats {
 
                         current_btb.blocks += 1;
 
-                        let mut well = None;
-                        let (col, height) = get_well(&placement.board);
-                        if height > 4 {
+                        if let Some(col) = well {
                             stats.well_cols[col] += 1;
-                            well = Some(col);
                         }
                         if current_btb.well != well {
                             current_btb.wellshifts += 1;
+                            wellshift = true;
                         }
                         current_btb.well = well;
                         Some(current_btb)
@@ -248,50 +419,96 @@ impl From<&[PlacementStats]> for CumulativePlacementStats {
                 }
             }
 
-            let (atk, def) = solve_state(
-                &placement.board,
+            let solved = solve_state(
+                &board,
                 placement.btb_chain,
                 placement.combo,
                 &placement.queue,
             );
+            if let Some((_, def)) = solved {
+                stats.defense_potentials.push(def);
+            }
+            stats.attack_potentials.push(solved.map(|(atk, _)| atk));
 
-            if atk >= 9 {
+            let mut score = None;
+            if solved.is_some_and(|(atk, _)| atk >= 9) {
                 //spikable board limit is around 2btb clears
                 stats.spikable_boards += 1;
             } else {
-                let mut bf_queue: Vec<_> = placement
-                    .queue
-                    .iter()
-                    .filter_map(|&mino| mino_to_color(mino))
-                    .take(5)
-                    .collect();
-                let bf_hold = bf_queue.remove(0);
-                let mut bf_matrix = blockfish::BasicMatrix::with_cols(10);
-                for y in 0..(40 - garbage_height) {
-                    for x in 0..10 {
-                        if placement.board[x + y * 10] != MinoType::Empty {
-                            bf_matrix.set(((39 - garbage_height - y) as u16, x as u16));
-                        }
-                    }
+                score = blockfish_score(&mut blockfish, &board, &placement.queue);
+                if let Some(score) = score {
+                    stats.blockfish_scores.push(score);
                 }
+            }
 
-                let analysis = blockfish.analyze_raw(blockfish::ai::Snapshot {
-                    hold: Some(bf_hold),
-                    queue: bf_queue,
-                    matrix: bf_matrix,
-                });
-                if analysis > 0 {
-                    stats.blockfish_scores.push(analysis as usize);
+            let incoming = placement.attack_received.iter().sum::<usize>();
+            let death_risk = get_death_risk(&board, &placement.queue, incoming);
+            if death_risk >= DANGER_RISK {
+                stats.danger_frames += round_delay(placement.frame_delay);
+            }
+            if death_risk >= NEAR_DEATH_RISK {
+                near_death = true;
+            } else if near_death && death_risk < DANGER_RISK {
+                //only count it once we're back out of danger
+                stats.near_death_recoveries += 1;
+                near_death = false;
+            }
+            stats.death_risks.push(death_risk);
+
+            let mut pc_solution = None;
+            if i >= pc_window_end && height > 0 {
+                if let Some(moves) = find_pc(&board, &placement.queue) {
+                    //the player has as many pieces as the solution to take the pc
+                    let taken = game
+                        .iter()
+                        .skip(i + 1)
+                        .take(moves.len())
+                        .any(|p| p.board.iter().all(|&mino| mino == MinoType::Empty));
+                    stats.pc_opportunities += 1;
+                    if taken {
+                        stats.pc_taken += 1;
+                    }
+                    pc_window_end = i + 1 + moves.len();
+                    let mut pages = vec![placement.board.clone()];
+                    for solver_move in &moves {
+                        let mut next = pages.last().unwrap().clone(); //starts with the board
+                        solver_move.place_on(&mut next, placement.board_width);
+                        pages.push(next);
+                    }
+                    pc_solution = Some(PcSolution {
+                        placement: i,
+                        taken,
+                        moves,
+                        fumen: fumen::encode(&pages),
+                    });
                 }
             }
 
-            stats.defense_potentials.push(def);
+            stats.placement_features.push(PlacementFeatures {
+                stack_height: height - garbage_height,
+                garbage_height,
+                well,
+                wellshift,
+                cheese: has_cheese(&board),
+                cheese_cleared: just_ate_cheese,
+                attack_potential: solved.map(|(atk, _)| atk),
+                defence_potential: solved.map(|(_, def)| def),
+                blockfish_score: score,
+                death_risk,
+                combo_segment: current_combo
+                    .is_some()
+                    .then_some(stats.combo_segments.len()),
+                btb_segment: current_btb.is_some().then_some(stats.btb_segments.len()),
+                garbage,
+                pc_solution,
+            });
 
             if spike_grace_period > 0 {
                 spike_grace_period -= 1;
             } else {
                 stats.pre_spike_boards += 1;
             }
+            previous_board = Some(board);
         }
         if let Some(current_combo) = current_combo {
             stats.combo_segments.push(current_combo);
@@ -299,10 +516,70 @@ impl From<&[PlacementStats]> for CumulativePlacementStats {
         if let Some(current_btb) = current_btb {
             stats.btb_segments.push(current_btb);
         }
+        //a segment is only known to be dug once the rest of the game has been played
+        let garbage_cleared: Vec<_> = game.iter().map(|p| p.garbage_cleared).collect();
+        for (i, features) in stats.placement_features.iter_mut().enumerate() {
+            mark_dug(&mut features.garbage, i, &garbage_cleared);
+        }
+
+        if let Some((opener, completed)) = recognize_opener(opener_boards.iter()) {
+            stats.openers.insert(
+                opener,
+                OpenerRecord {
+                    games: 1,
+                    completed: completed as usize,
+                    attack: stats.opener_attack,
+                    frames: stats.opener_frames,
+                },
+            );
+        }
+
+        stats.garbage_chunks = garbage_ledger.finish();
+        //garbage that was received but never made it onto the board
+        stats.garbage_cancelled = stats.garbage_chunks.iter().map(|c| c.cancelled).sum();
 
         stats
     }
 }
+///what the game loop works out for a single placement
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlacementFeatures {
+    pub stack_height: usize,
+    pub garbage_height: usize,
+    ///column of the well when it's more than 4 deep
+    pub well: Option<usize>,
+    ///the well moved while in a btb chain
+    pub wellshift: bool,
+    pub cheese: bool,
+    ///cleared garbage off a board that had cheese
+    pub cheese_cleared: bool,
+    pub attack_potential: Option<usize>,
+    pub defence_potential: Option<usize>,
+    ///only looked for on boards that can't spike
+    pub blockfish_score: Option<usize>,
+    pub death_risk: f64,
+    ///index into the game's combo segments, none outside a combo
+    pub combo_segment: Option<usize>,
+    ///index into the game's btb segments, none outside a btb chain
+    pub btb_segment: Option<usize>,
+    ///the garbage on the board split into segments, with where each one was dug out
+    pub garbage: GarbageAnalysis,
+    ///the perfect clear found on the board, boards covered by an earlier solution aren't searched
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pc_solution: Option<PcSolution>,
+}
+
+///a perfect clear that was available after a placement, `placement` is the index into its game
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PcSolution {
+    pub placement: usize,
+    pub taken: bool,
+    pub moves: Vec<SolverMove>,
+    ///the board followed by a page for every move
+    pub fumen: Option<String>,
+}
+
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 
 pub struct BTBSegment {
@@ -373,6 +650,41 @@ impl ComboSegment {
     }
 }
 
+pub(crate) fn new_blockfish() -> blockfish::ai::AI {
+    let blockfish_config = blockfish::Config {
+        search_limit: 100,
+        parameters: blockfish::Parameters::default(),
+    };
+    blockfish::ai::AI::new(blockfish_config)
+}
+
+///blockfish's score of the board above the garbage, the first piece in the queue is the hold
+///
+///none without any pieces to place
+pub(crate) fn blockfish_score(
+    blockfish: &mut blockfish::ai::AI,
+    board: &BitBoard,
+    queue: &[MinoType],
+) -> Option<usize> {
+    let mut bf_queue: Vec<_> = queue
+        .iter()
+        .filter_map(|&mino| mino_to_color(mino))
+        .take(5)
+        .collect();
+    if bf_queue.is_empty() {
+        return None;
+    }
+    let bf_hold = bf_queue.remove(0);
+    let bf_matrix = board.to_basic_matrix(get_garbage_height(board));
+
+    let analysis = blockfish.analyze_raw(blockfish::ai::Snapshot {
+        hold: Some(bf_hold),
+        queue: bf_queue,
+        matrix: bf_matrix,
+    });
+    (analysis > 0).then_some(analysis as usize)
+}
+
 fn mino_to_color(mino: MinoType) -> Option<blockfish::Color> {
     match mino {
         MinoType::Z => blockfish::Color::try_from('Z').ok(),
@@ -386,6 +698,6 @@ fn mino_to_color(mino: MinoType) -> Option<blockfish::Color> {
     }
 }
 
-fn round_delay(delay: f64) -> f64 {
+pub(crate) fn round_delay(delay: f64) -> f64 {
     (delay * 10.0).round() / 10.0
 }
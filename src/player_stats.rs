@@ -1,74 +1,224 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use crate::{
-    placement_stats::CumulativePlacementStats,
+    board_analyzer::BoardFeatures,
+    confidence::Ratio,
+    distribution::Distributions,
+    openers::Opener,
+    placement_stats::{BTBSegment, ComboSegment, CumulativePlacementStats},
+    rating::TradeStats,
     replay_response::{ClearType, MinoType},
+    setups::{Setup, SETUP_COUNT},
 };
-use serde::Serialize;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Default, Debug)]
-#[serde(rename_all = "camelCase")]
+#[derive(Serialize, Deserialize, JsonSchema, Default, Debug)]
+#[serde(rename_all = "camelCase", default)]
 pub struct PlayerStats {
-    pub well_columns: [usize; 10],
+    pub well_columns: Vec<usize>,
     pub clear_types: HashMap<ClearType, usize>,
-
-    pub t_efficiency: f64,
-    pub i_efficiency: f64,
-
-    pub cheese_apl: f64,
-    pub downstack_apl: f64,
-    pub upstack_apl: f64,
-
-    pub apl: f64,
-    pub app: f64,
-
-    pub kpp: f64,
-    pub kps: f64,
-
-    pub stack_height: f64,
-    pub garbage_height: f64,
-
-    pub spike_efficiency: f64,
-
-    pub apm: f64,
-    pub opener_apm: f64,
-    pub midgame_apm: f64,
-
-    pub pps: f64,
-    pub opener_pps: f64,
-    pub midgame_pps: f64,
+    pub setups: HashMap<Setup, SetupStats>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub t_efficiency: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub i_efficiency: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cheese_apl: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub downstack_apl: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upstack_apl: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clean_downstack_apl: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cheese_downstack_apl: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dig_speed: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dig_per_piece: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub average_dig_efficiency: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub average_messiness: Option<f64>,
+    pub clean_segments_dug: usize,
+    pub messy_segments_dug: usize,
+    pub garbage_cancelled: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cancel_rate: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tank_rate: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub average_dig_time: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub garbage_per_minute_received: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub apl: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub app: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kpp: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kps: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stack_height: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub garbage_height: Option<f64>,
+
+    pub average_column_heights: Vec<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub average_bumpiness: Option<f64>,
+    pub max_bumpiness: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub average_holes: Option<f64>,
+    pub max_holes: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub average_hole_depth: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub average_covered_cells: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub average_overhangs: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub average_row_transitions: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub average_column_transitions: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub average_wells: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub average_well_depth: Option<f64>,
+    pub max_well_depth: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub average_t_slots: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub average_parity: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spike_efficiency: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub apm: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub opener_apm: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub midgame_apm: Option<f64>,
+    pub openers: HashMap<Opener, OpenerStats>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pps: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub opener_pps: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub midgame_pps: Option<f64>,
     pub btb_wellshifts: usize,
 
-    pub btb_chain_efficiency: f64,
-    pub btb_chain: f64,
-    pub btb_chain_apm: f64,
-    pub btb_chain_attack: f64,
-    pub btb_chain_wellshifts: f64,
-    pub btb_chain_app: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub btb_chain_efficiency: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub btb_chain: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub btb_chain_apm: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub btb_chain_attack: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub btb_chain_wellshifts: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub btb_chain_app: Option<f64>,
 
     pub max_btb: usize,
     pub max_btb_attack: usize,
 
-    pub combo_chain_efficiency: f64,
-    pub combo_chain: f64,
-    pub combo_chain_apm: f64,
-    pub combo_chain_attack: f64,
-    pub combo_chain_app: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub combo_chain_efficiency: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub combo_chain: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub combo_chain_apm: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub combo_chain_attack: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub combo_chain_app: Option<f64>,
 
     pub max_combo: usize,
     pub max_combo_attack: usize,
 
-    pub average_spike_potential: f64,
-    pub average_defence_potential: f64,
-
-    pub pps_variance: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub average_spike_potential: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub average_defence_potential: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vs: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ds_per_second: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ds_per_piece: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cheese_index: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub garbage_efficiency: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub area: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub estimated_glicko: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub estimated_tr: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pps_variance: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blockfish_score: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub burst_pps: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attack_delay_rate: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pre_attack_delay_rate: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub average_death_risk: Option<f64>,
+    pub time_in_danger: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub danger_rate: Option<f64>,
+    pub near_death_recoveries: usize,
+
+    pub pc_opportunities: usize,
+    pub pc_taken: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pc_conversion_rate: Option<f64>,
+
+    pub distributions: Distributions,
+    ///sample size and 95% interval behind each of the ratios above
+    pub confidence: BTreeMap<String, Ratio>,
+}
 
-    pub blockfish_score: f64,
+#[derive(Serialize, Deserialize, JsonSchema, Default, Debug)]
+#[serde(rename_all = "camelCase", default)]
+pub struct OpenerStats {
+    pub games: usize,
+    pub completed: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completion_rate: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub apm: Option<f64>,
+}
 
-    pub burst_pps: f64,
-    pub attack_delay_rate: f64,
-    pub pre_attack_delay_rate: f64,
+#[derive(Serialize, Deserialize, JsonSchema, Default, Debug)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SetupStats {
+    pub built: usize,
+    pub cashed: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub conversion_rate: Option<f64>,
 }
+
 #[derive(Debug, Clone, Copy)]
 struct Burst {
     blocks: usize,
@@ -77,6 +227,27 @@ struct Burst {
 
 impl From<&CumulativePlacementStats> for PlayerStats {
     fn from(stats: &CumulativePlacementStats) -> Self {
+        Self::new(stats, 0)
+    }
+}
+
+///none for the NaN or infinity an empty denominator gives, which json can't hold
+fn finite(value: f64) -> Option<f64> {
+    value.is_finite().then_some(value)
+}
+
+///name a map key is written under, like `STSD` for a setup
+fn key_name<T: Serialize>(key: T) -> String {
+    serde_json::to_value(key)
+        .ok()
+        .and_then(|key| key.as_str().map(String::from))
+        .unwrap_or_default()
+}
+
+impl PlayerStats {
+    ///ratios backed by fewer than `min_samples` samples or by nothing at all are left out instead of coming
+    ///out as NaN
+    pub fn new(stats: &CumulativePlacementStats, min_samples: usize) -> Self {
         let tspins = stats.clear_types[ClearType::TspinDouble as usize]
             + stats.clear_types[ClearType::TspinMiniDouble as usize]
             + stats.clear_types[ClearType::TspinSingle as usize]
@@ -119,14 +290,6 @@ impl From<&CumulativePlacementStats> for PlayerStats {
             .iter()
             .filter(|segment| segment.blocks > 4)
             .collect();
-        let true_combo_chain_blocks = true_combo_chains
-            .iter()
-            .map(|seg| seg.blocks)
-            .sum::<usize>() as f64;
-        let true_combo_chain_attack = true_combo_chains
-            .iter()
-            .map(|segment| segment.attack)
-            .sum::<usize>() as f64;
 
         let true_btb_chains: Vec<_> = stats
             .btb_segments
@@ -138,12 +301,6 @@ impl From<&CumulativePlacementStats> for PlayerStats {
             .iter()
             .map(|segment| segment.wellshifts)
             .sum::<usize>();
-        let true_btb_chain_blocks =
-            true_btb_chains.iter().map(|seg| seg.blocks).sum::<usize>() as f64;
-        let true_btb_chain_attack = true_btb_chains
-            .iter()
-            .map(|segment| segment.attack)
-            .sum::<usize>() as f64;
 
         let mut clear_types = HashMap::new();
 
@@ -153,6 +310,34 @@ impl From<&CumulativePlacementStats> for PlayerStats {
                 stats.clear_types[clear_type as usize],
             );
         }
+        let mut confidence = BTreeMap::new();
+        //named like the field's path so the interval can be found from the number it belongs to
+        let mut ratio = |name: &str, ratio: Ratio| {
+            if ratio.samples == 0 || ratio.samples < min_samples || !ratio.value.is_finite() {
+                return None;
+            }
+            confidence.insert(name.to_string(), ratio);
+            Some(ratio.value)
+        };
+
+        let mut setups = HashMap::new();
+        for setup in 0..SETUP_COUNT {
+            let built = stats.setups_built[setup];
+            let cashed = stats.setups_cashed[setup];
+            let setup = Setup::try_from(setup as u8).unwrap();
+            let conversion_rate = ratio(
+                &format!("setups.{}.conversionRate", key_name(setup)),
+                Ratio::wilson(cashed, built),
+            );
+            setups.insert(
+                setup,
+                SetupStats {
+                    built,
+                    cashed,
+                    conversion_rate,
+                },
+            );
+        }
         let segment_times: Vec<_> = (0..stats.delays.len().saturating_sub(6))
             .map(|start| {
                 let seg: Vec<_> = stats.delays.iter().skip(start).take(7).cloned().collect();
@@ -199,51 +384,298 @@ impl From<&CumulativePlacementStats> for PlayerStats {
         let prev_attack_chains: Vec<_> =
             attack_chains.iter().filter_map(|c| c.prev_delay).collect();
 
+        let openers = stats
+            .openers
+            .iter()
+            .map(|(&opener, record)| {
+                let name = key_name(opener);
+                (
+                    opener,
+                    OpenerStats {
+                        games: record.games,
+                        completed: record.completed,
+                        completion_rate: ratio(
+                            &format!("openers.{}.completionRate", name),
+                            Ratio::wilson(record.completed, record.games),
+                        ),
+                        apm: ratio(
+                            &format!("openers.{}.apm", name),
+                            Ratio::per_minute(record.attack, record.frames, record.games),
+                        ),
+                    },
+                )
+            })
+            .collect();
+
+        let features = &stats.board_features;
+        let chain_samples = |sample: fn(&BTBSegment) -> (f64, f64)| {
+            true_btb_chains
+                .iter()
+                .map(|&seg| sample(seg))
+                .collect::<Vec<_>>()
+        };
+        let combo_samples = |sample: fn(&ComboSegment) -> (f64, f64)| {
+            true_combo_chains
+                .iter()
+                .map(|&seg| sample(seg))
+                .collect::<Vec<_>>()
+        };
+        let t_efficiency = ratio(
+            "tEfficiency",
+            Ratio::wilson(tspins, stats.shape_types[MinoType::T as usize]),
+        );
+        let i_efficiency = ratio(
+            "iEfficiency",
+            Ratio::wilson(
+                stats.clear_types[ClearType::Quad as usize],
+                stats.shape_types[MinoType::I as usize],
+            ),
+        );
+        let cheese_apl = ratio(
+            "cheeseApl",
+            Ratio::poisson(stats.attack_with_cheese, stats.exclusive_cheese_cleared),
+        );
+        let downstack_apl = ratio(
+            "downstackApl",
+            Ratio::poisson(stats.attack_with_garbage, stats.exclusive_garbage_cleared),
+        );
+        let upstack_apl = ratio(
+            "upstackApl",
+            Ratio::poisson(stats.attack_with_stack, stats.exclusive_stack_cleared),
+        );
+        let clean_downstack_apl = ratio(
+            "cleanDownstackApl",
+            Ratio::poisson(
+                stats.attack_with_clean_garbage,
+                stats.exclusive_clean_garbage_cleared,
+            ),
+        );
+        let cheese_downstack_apl = ratio(
+            "cheeseDownstackApl",
+            Ratio::poisson(
+                stats.attack_with_messy_garbage,
+                stats.exclusive_messy_garbage_cleared,
+            ),
+        );
+        let dig_per_piece = ratio(
+            "digPerPiece",
+            Ratio::poisson(stats.dig_garbage_cleared, stats.dig_pieces),
+        );
+        let average_dig_efficiency = ratio(
+            "averageDigEfficiency",
+            Ratio::wilson(stats.dig_garbage_cleared, stats.dig_lines_cleared),
+        );
+        let apl = ratio("apl", Ratio::poisson(stats.attack, stats.lines_cleared));
+        let app = ratio("app", Ratio::poisson(stats.attack, stats.delays.len()));
+        let btb_chain_efficiency = ratio(
+            "btbChainEfficiency",
+            Ratio::wilson(true_btb_chains.len(), stats.btb_segments.len()),
+        );
+        let btb_chain = ratio(
+            "btbChain",
+            Ratio::bootstrap(&chain_samples(|seg| (seg.btb as f64, 1.0))),
+        );
+        let btb_chain_apm = ratio(
+            "btbChainApm",
+            Ratio::bootstrap(&chain_samples(|seg| {
+                (seg.attack as f64, seg.frames / 3600.0)
+            })),
+        );
+        let btb_chain_attack = ratio(
+            "btbChainAttack",
+            Ratio::bootstrap(&chain_samples(|seg| (seg.attack as f64, 1.0))),
+        );
+        let btb_chain_wellshifts = ratio(
+            "btbChainWellshifts",
+            Ratio::bootstrap(&chain_samples(|seg| (seg.wellshifts as f64, 1.0))),
+        );
+        let btb_chain_app = ratio(
+            "btbChainApp",
+            Ratio::bootstrap(&chain_samples(|seg| (seg.attack as f64, seg.blocks as f64))),
+        );
+        let combo_chain_efficiency = ratio(
+            "comboChainEfficiency",
+            Ratio::wilson(true_combo_chains.len(), stats.combo_segments.len()),
+        );
+        let combo_chain = ratio(
+            "comboChain",
+            Ratio::bootstrap(&combo_samples(|seg| ((seg.blocks - 1) as f64, 1.0))),
+        );
+        let combo_chain_apm = ratio(
+            "comboChainApm",
+            Ratio::bootstrap(&combo_samples(|seg| {
+                (seg.attack as f64, seg.frames / 3600.0)
+            })),
+        );
+        let combo_chain_attack = ratio(
+            "comboChainAttack",
+            Ratio::bootstrap(&combo_samples(|seg| (seg.attack as f64, 1.0))),
+        );
+        let combo_chain_app = ratio(
+            "comboChainApp",
+            Ratio::bootstrap(&combo_samples(|seg| (seg.attack as f64, seg.blocks as f64))),
+        );
+        let pc_conversion_rate = ratio(
+            "pcConversionRate",
+            Ratio::wilson(stats.pc_taken, stats.pc_opportunities),
+        );
+
+        let trade = TradeStats::new(
+            stats.attack as f64 * 60.0 / time_secs,
+            blocks / time_secs,
+            (stats.attack + stats.garbage_cleared) as f64 * 100.0 / time_secs,
+        );
+
+        let garbage_received = stats.garbage_chunks.iter().map(|c| c.amount).sum::<usize>();
+        let garbage_tanked = stats.garbage_chunks.iter().map(|c| c.tanked).sum::<usize>();
+        let dig_times: Vec<_> = stats
+            .garbage_chunks
+            .iter()
+            .filter_map(|chunk| chunk.dig_time())
+            .map(|time| (time as f64, 1.0))
+            .collect();
+        let average_dig_time = ratio("averageDigTime", Ratio::bootstrap(&dig_times));
+        let messiness: Vec<_> = stats
+            .garbage_messiness
+            .iter()
+            .map(|&messiness| (messiness, 1.0))
+            .collect();
+        let average_messiness = ratio("averageMessiness", Ratio::bootstrap(&messiness));
+        let cancel_rate = ratio(
+            "cancelRate",
+            Ratio::wilson(stats.garbage_cancelled, garbage_received),
+        );
+        let tank_rate = ratio("tankRate", Ratio::wilson(garbage_tanked, garbage_received));
+        //the headline rates get an interval too so a diff can tell whether they really changed
+        let placements = stats.delays.len();
+        let per_second =
+            |count: usize| Ratio::per_minute(count, time_frames, placements).scaled(1.0 / 60.0);
+        let apm = ratio(
+            "apm",
+            Ratio::per_minute(stats.attack, time_frames, placements),
+        );
+        let pps = ratio("pps", per_second(placements));
+        let kps = ratio("kps", per_second(stats.keypresses));
+        let vs = ratio(
+            "vs",
+            per_second(stats.attack + stats.garbage_cleared).scaled(100.0),
+        );
+        let stack_heights: Vec<_> = stats
+            .stack_heights
+            .iter()
+            .map(|&height| (height as f64, 1.0))
+            .collect();
+        let stack_height = ratio("stackHeight", Ratio::bootstrap(&stack_heights));
+        let opener_apm = ratio(
+            "openerApm",
+            Ratio::per_minute(
+                stats.opener_attack,
+                stats.opener_frames,
+                stats.opener_blocks,
+            ),
+        );
+        let midgame_apm = ratio(
+            "midgameApm",
+            Ratio::per_minute(
+                stats.attack - stats.opener_attack,
+                time_frames - stats.opener_frames,
+                stats.delays.len().saturating_sub(stats.opener_blocks),
+            ),
+        );
+        let delayed = |delays: &mut dyn Iterator<Item = f64>| {
+            delays
+                .filter(|&delay| delay - frame_average > frame_sd)
+                .count()
+        };
+        let attack_delay_rate = ratio(
+            "attackDelayRate",
+            Ratio::wilson(
+                delayed(&mut attack_chains.iter().map(|segment| segment.initial_delay)),
+                attack_chains.len(),
+            ),
+        );
+        let pre_attack_delay_rate = ratio(
+            "preAttackDelayRate",
+            Ratio::wilson(
+                delayed(&mut prev_attack_chains.iter().copied()),
+                prev_attack_chains.len(),
+            ),
+        );
+        let bursts: Vec<_> = bursts
+            .iter()
+            .map(|burst| (burst.blocks as f64, burst.delay / 60.0))
+            .collect();
+        let burst_pps = ratio("burstPps", Ratio::bootstrap(&bursts));
+
+        let average_feature = |feature: fn(&BoardFeatures) -> usize| {
+            finite(features.iter().map(feature).sum::<usize>() as f64 / features.len() as f64)
+        };
+        let max_feature =
+            |feature: fn(&BoardFeatures) -> usize| features.iter().map(feature).max().unwrap_or(0);
+        //without any boards there are no heights to average
+        let columns = if features.is_empty() {
+            0
+        } else {
+            stats.well_cols.len()
+        };
+        let average_column_heights = (0..columns)
+            .map(|x| average_feature_at(features, x))
+            .collect();
+
         Self {
-            well_columns: stats.well_cols,
+            well_columns: stats.well_cols.clone(),
             clear_types,
-            t_efficiency: tspins as f64 / stats.shape_types[MinoType::T as usize] as f64,
-            i_efficiency: stats.clear_types[ClearType::Quad as usize] as f64
-                / stats.shape_types[MinoType::I as usize] as f64,
-            cheese_apl: stats.attack_with_cheese as f64 / stats.exclusive_cheese_cleared as f64,
-            downstack_apl: stats.attack_with_garbage as f64
-                / stats.exclusive_garbage_cleared as f64,
-            upstack_apl: stats.attack_with_stack as f64 / stats.exclusive_stack_cleared as f64,
-            apl: stats.attack as f64 / stats.lines_cleared as f64,
-            app: stats.attack as f64 / blocks,
-            kpp: stats.keypresses as f64 / blocks,
-            kps: stats.keypresses as f64 / time_secs,
-            stack_height: stats.stack_heights.iter().sum::<usize>() as f64
-                / stats.stack_heights.len() as f64,
-            garbage_height: stats.garbage_heights.iter().sum::<usize>() as f64
-                / stats.garbage_heights.len() as f64,
-            spike_efficiency: stats
-                .combo_segments
-                .iter()
-                .filter(|segment| segment.attack >= 10)
-                .map(|segment| segment.blocks)
-                .sum::<usize>() as f64
-                / blocks,
-            apm: stats.attack as f64 * 60.0 / time_secs,
-            opener_apm: (stats.opener_attack as f64 / opener_time_secs) * 60.0,
-            midgame_apm: ((stats.attack - stats.opener_attack) as f64
-                / (time_secs - opener_time_secs))
-                * 60.0,
-            opener_pps: stats.opener_blocks as f64 / opener_time_secs,
-            midgame_pps: (blocks - stats.opener_blocks as f64) / (time_secs - opener_time_secs),
-            pps: blocks / time_secs,
+            setups,
+            dig_speed: finite(stats.garbage_cleared as f64 * 60.0 / time_secs),
+            average_messiness,
+            clean_segments_dug: stats.clean_segments_dug,
+            messy_segments_dug: stats.messy_segments_dug,
+            garbage_cancelled: stats.garbage_cancelled,
+            cancel_rate,
+            tank_rate,
+            average_dig_time,
+            garbage_per_minute_received: finite(garbage_received as f64 * 60.0 / time_secs),
+            kpp: finite(stats.keypresses as f64 / blocks),
+            kps,
+            stack_height,
+            garbage_height: finite(
+                stats.garbage_heights.iter().sum::<usize>() as f64
+                    / stats.garbage_heights.len() as f64,
+            ),
+            average_column_heights,
+            average_bumpiness: average_feature(|f| f.bumpiness),
+            max_bumpiness: max_feature(|f| f.bumpiness),
+            average_holes: average_feature(|f| f.holes),
+            max_holes: max_feature(|f| f.holes),
+            average_hole_depth: average_feature(|f| f.hole_depth),
+            average_covered_cells: average_feature(|f| f.covered_cells),
+            average_overhangs: average_feature(|f| f.overhangs),
+            average_row_transitions: average_feature(|f| f.row_transitions),
+            average_column_transitions: average_feature(|f| f.column_transitions),
+            average_wells: average_feature(|f| f.wells),
+            average_well_depth: average_feature(|f| f.well_depth),
+            max_well_depth: max_feature(|f| f.well_depth),
+            average_t_slots: average_feature(|f| f.t_slots),
+            average_parity: average_feature(|f| f.parity),
+            spike_efficiency: finite(
+                stats
+                    .combo_segments
+                    .iter()
+                    .filter(|segment| segment.attack >= 10)
+                    .map(|segment| segment.blocks)
+                    .sum::<usize>() as f64
+                    / blocks,
+            ),
+            apm,
+            opener_apm,
+            midgame_apm,
+            openers,
+            opener_pps: finite(stats.opener_blocks as f64 / opener_time_secs),
+            midgame_pps: finite(
+                (blocks - stats.opener_blocks as f64) / (time_secs - opener_time_secs),
+            ),
+            pps,
             btb_wellshifts: wellshifts,
-            btb_chain_wellshifts: wellshifts as f64 / true_btb_chains.len() as f64,
-            btb_chain_efficiency: true_btb_chains.len() as f64 / stats.btb_segments.len() as f64,
-            btb_chain: true_btb_chains
-                .iter()
-                .map(|segment| segment.btb)
-                .sum::<usize>() as f64
-                / true_btb_chains.len() as f64,
-            btb_chain_apm: true_btb_chain_attack
-                / true_btb_chains.iter().map(|seg| seg.frames).sum::<f64>()
-                * 3600.0,
-            btb_chain_attack: true_btb_chain_attack / true_btb_chains.len() as f64,
             max_btb: stats
                 .btb_segments
                 .iter()
@@ -256,17 +688,6 @@ impl From<&CumulativePlacementStats> for PlayerStats {
                 .map(|segment| segment.attack)
                 .max()
                 .unwrap_or(0),
-            combo_chain_efficiency: true_combo_chains.len() as f64
-                / stats.combo_segments.len() as f64,
-            combo_chain: true_combo_chains
-                .iter()
-                .map(|seg| seg.blocks - 1)
-                .sum::<usize>() as f64
-                / true_combo_chains.len() as f64,
-            combo_chain_apm: true_combo_chain_attack
-                / true_combo_chains.iter().map(|seg| seg.frames).sum::<f64>()
-                * 3600.0,
-            combo_chain_attack: true_combo_chain_attack / true_combo_chains.len() as f64,
             max_combo: stats
                 .combo_segments
                 .iter()
@@ -279,26 +700,123 @@ impl From<&CumulativePlacementStats> for PlayerStats {
                 .map(|segment| segment.attack)
                 .max()
                 .unwrap_or(0),
-            average_spike_potential: stats.spikable_boards as f64 / stats.pre_spike_boards as f64,
-            average_defence_potential: stats.defense_potentials.iter().sum::<usize>() as f64
-                / blocks,
-            btb_chain_app: true_btb_chain_attack / true_btb_chain_blocks,
-            combo_chain_app: true_combo_chain_attack / true_combo_chain_blocks,
-            pps_variance: frame_sd / frame_average,
-            blockfish_score: stats.blockfish_scores.iter().sum::<usize>() as f64
-                / stats.blockfish_scores.len() as f64,
-            attack_delay_rate: attack_chains
-                .iter()
-                .filter(|segment| segment.initial_delay - frame_average > frame_sd)
-                .count() as f64
-                / (attack_chains.len() as f64),
-            pre_attack_delay_rate: prev_attack_chains
-                .iter()
-                .filter(|&&segment| segment - frame_average > frame_sd)
-                .count() as f64
-                / (prev_attack_chains.len() as f64),
-            burst_pps: bursts.iter().map(|burst| burst.blocks).sum::<usize>() as f64
-                / (bursts.iter().map(|burst| burst.delay).sum::<f64>() / 60.0),
+            average_spike_potential: finite(
+                stats.spikable_boards as f64 / stats.pre_spike_boards as f64,
+            ),
+            average_defence_potential: finite(
+                stats.defense_potentials.iter().sum::<usize>() as f64
+                    / stats.defense_potentials.len() as f64,
+            ),
+            vs,
+            ds_per_second: finite(trade.ds_per_second),
+            ds_per_piece: finite(trade.ds_per_piece),
+            cheese_index: finite(trade.cheese_index),
+            garbage_efficiency: finite(trade.garbage_efficiency),
+            area: finite(trade.area),
+            estimated_glicko: finite(trade.estimated_glicko),
+            estimated_tr: finite(trade.estimated_tr),
+            pps_variance: finite(frame_sd / frame_average),
+            blockfish_score: finite(
+                stats.blockfish_scores.iter().sum::<usize>() as f64
+                    / stats.blockfish_scores.len() as f64,
+            ),
+            attack_delay_rate,
+            pre_attack_delay_rate,
+            burst_pps,
+            average_death_risk: finite(
+                stats.death_risks.iter().sum::<f64>() / stats.death_risks.len() as f64,
+            ),
+            time_in_danger: stats.danger_frames / 60.0,
+            danger_rate: finite(stats.danger_frames / time_frames),
+            near_death_recoveries: stats.near_death_recoveries,
+            pc_opportunities: stats.pc_opportunities,
+            pc_taken: stats.pc_taken,
+            distributions: Distributions::from(stats),
+            t_efficiency,
+            i_efficiency,
+            cheese_apl,
+            downstack_apl,
+            upstack_apl,
+            clean_downstack_apl,
+            cheese_downstack_apl,
+            dig_per_piece,
+            average_dig_efficiency,
+            apl,
+            app,
+            btb_chain_efficiency,
+            btb_chain,
+            btb_chain_apm,
+            btb_chain_attack,
+            btb_chain_wellshifts,
+            btb_chain_app,
+            combo_chain_efficiency,
+            combo_chain,
+            combo_chain_apm,
+            combo_chain_attack,
+            combo_chain_app,
+            pc_conversion_rate,
+            confidence,
         }
     }
 }
+
+fn average_feature_at(features: &[BoardFeatures], column: usize) -> f64 {
+    features
+        .iter()
+        .filter_map(|f| f.column_heights.get(column))
+        .sum::<usize>() as f64
+        / features.len() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay_response::PlacementStats;
+    use crate::test_support;
+
+    #[test]
+    fn ratios_with_nothing_behind_them_are_left_out() {
+        let stats = PlayerStats::new(&CumulativePlacementStats::default(), 0);
+        assert_eq!(stats.cancel_rate, None);
+        assert_eq!(stats.tank_rate, None);
+        assert_eq!(stats.average_dig_time, None);
+        assert_eq!(stats.average_messiness, None);
+        assert_eq!(stats.opener_apm, None);
+        assert_eq!(stats.midgame_apm, None);
+        assert_eq!(stats.attack_delay_rate, None);
+        assert_eq!(stats.burst_pps, None);
+        assert!(stats
+            .setups
+            .values()
+            .all(|setup| setup.conversion_rate.is_none()));
+        assert!(stats.confidence.is_empty());
+    }
+
+    #[test]
+    fn vs_counts_attack_and_garbage_cleared_per_second() {
+        //a minute of 2 pps with 60 attack and 12 garbage cleared
+        let game: Vec<_> = (0..120)
+            .map(|i| PlacementStats {
+                frame_delay: 30.0,
+                attack: if i % 2 == 0 { vec![1] } else { Vec::new() },
+                lines_cleared: (i % 10 == 1) as usize,
+                garbage_cleared: (i % 10 == 1) as usize,
+                ..test_support::placement()
+            })
+            .collect();
+        let stats = PlayerStats::from(&CumulativePlacementStats::from(&game[..]));
+        let close = |value: Option<f64>, expected: f64| {
+            value.is_some_and(|value| (value - expected).abs() < 1e-6)
+        };
+        assert!(close(stats.apm, 60.0), "apm {:?}", stats.apm);
+        assert!(close(stats.pps, 2.0), "pps {:?}", stats.pps);
+        assert!(close(stats.vs, 120.0), "vs {:?}", stats.vs);
+        assert!(
+            close(stats.ds_per_piece, 0.1),
+            "ds/piece {:?}",
+            stats.ds_per_piece
+        );
+        assert!(close(stats.cheese_index, 27.5));
+        assert!(close(stats.area, 407.28));
+    }
+}
@@ -0,0 +1,193 @@
+use crate::bitboard::BitBoard;
+use crate::fumen;
+use crate::placement_stats::{blockfish_score, new_blockfish};
+use crate::replay_response::{Board, MinoType, MAX_BOARD_WIDTH};
+use crate::solver::{best_placements, solve_state, SolverMove};
+use serde::Serialize;
+
+///suggested placements kept in the analysis
+const BEST_PLACEMENTS: usize = 5;
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SuggestedPlacement {
+    #[serde(flatten)]
+    pub placement: SolverMove,
+    pub attack_potential: usize,
+    pub defence_potential: usize,
+    ///the board with the placement made
+    pub fumen: Option<String>,
+}
+
+///what the game loop works out for a placement, for a single board on its own
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PositionAnalysis {
+    pub attack_potential: Option<usize>,
+    pub defence_potential: Option<usize>,
+    pub blockfish_score: Option<usize>,
+    ///every placement of the current or hold piece, best attack first
+    pub best_placements: Vec<SuggestedPlacement>,
+    pub fumen: Option<String>,
+}
+
+///analyzes a board stored top row first, `queue` starts with the current piece
+///
+///the solver only looks at boards up to 10 wide and only boards exactly 10 wide get a fumen, without a hold or
+///queue the board only gets its fumen
+pub fn analyze_position(
+    board: &Board,
+    width: usize,
+    hold: MinoType,
+    queue: &[MinoType],
+    btb: usize,
+    combo: usize,
+) -> PositionAnalysis {
+    let bitboard = BitBoard::new(board, width);
+    let queue: Vec<_> = std::iter::once(hold).chain(queue.iter().copied()).collect();
+    let solved = solve_state(&bitboard, btb, combo, &queue);
+    let is_fumen_width = width == fumen::FUMEN_WIDTH;
+
+    let best_placements = best_placements(&bitboard, btb, combo, &queue)
+        .unwrap_or_default()
+        .into_iter()
+        .take(BEST_PLACEMENTS)
+        .map(|(placement, attack_potential, defence_potential)| {
+            let fumen = is_fumen_width.then(|| {
+                let mut next = board.clone();
+                placement.place_on(&mut next, width);
+                fumen::encode(&[board.clone(), next])
+            });
+            SuggestedPlacement {
+                placement,
+                attack_potential,
+                defence_potential,
+                fumen: fumen.flatten(),
+            }
+        })
+        .collect();
+
+    PositionAnalysis {
+        attack_potential: solved.map(|(atk, _)| atk),
+        defence_potential: solved.map(|(_, def)| def),
+        blockfish_score: blockfish_score(&mut new_blockfish(), &bitboard, &queue),
+        best_placements,
+        fumen: is_fumen_width
+            .then(|| fumen::encode(std::slice::from_ref(board)))
+            .flatten(),
+    }
+}
+
+#[derive(Debug)]
+pub struct PositionError(pub String);
+impl std::fmt::Display for PositionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("Invalid position: {}", self.0))?;
+        Ok(())
+    }
+}
+impl std::error::Error for PositionError {}
+
+///a board as a json array of pieces `width` wide, or a fumen which is always 10 wide
+///
+///boards wider than `MAX_BOARD_WIDTH` don't fit a packed row and are refused
+///
+///returns the board with its width
+pub fn parse_board(text: &str, width: usize) -> Result<(Board, usize), PositionError> {
+    let text = text.trim();
+    if !text.starts_with('[') {
+        let board = fumen::decode(text)
+            .map_err(|error| PositionError(error.to_string()))?
+            .swap_remove(0);
+        return Ok((board, fumen::FUMEN_WIDTH));
+    }
+    if width > MAX_BOARD_WIDTH {
+        return Err(PositionError(format!(
+            "boards are at most {} wide, not {}",
+            MAX_BOARD_WIDTH, width
+        )));
+    }
+    let board: Board =
+        serde_json::from_str(text).map_err(|error| PositionError(error.to_string()))?;
+    if width == 0 || board.is_empty() || !board.len().is_multiple_of(width) {
+        return Err(PositionError(format!(
+            "{} cells can't make a board {} wide",
+            board.len(),
+            width
+        )));
+    }
+    Ok((board, width))
+}
+
+///pieces as letters like `TIOSZ` or as a json array
+pub fn parse_queue(text: &str) -> Result<Vec<MinoType>, PositionError> {
+    let text = text.trim();
+    if text.starts_with('[') {
+        return serde_json::from_str(text).map_err(|error| PositionError(error.to_string()));
+    }
+    text.chars()
+        .map(|c| match c.to_ascii_uppercase() {
+            'Z' => Ok(MinoType::Z),
+            'L' => Ok(MinoType::L),
+            'O' => Ok(MinoType::O),
+            'S' => Ok(MinoType::S),
+            'I' => Ok(MinoType::I),
+            'J' => Ok(MinoType::J),
+            'T' => Ok(MinoType::T),
+            _ => Err(PositionError(format!("{} isn't a piece", c))),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::board;
+
+    #[test]
+    fn boards_are_read_from_json_or_fumen() {
+        let json = serde_json::to_string(&board(&["#########."])).unwrap();
+        assert_eq!(
+            parse_board(&json, 10).unwrap(),
+            (board(&["#########."]), 10)
+        );
+        assert_eq!(
+            parse_board("v115@bhI8KeAgH", 4).unwrap(),
+            (board(&["#########."]), 10)
+        );
+        //400 cells don't split into rows of 7
+        assert!(parse_board(&json, 7).is_err());
+        assert!(parse_board(&json, 0).is_err());
+    }
+
+    #[test]
+    fn boards_wider_than_a_packed_row_are_refused() {
+        let json = serde_json::to_string(&vec![MinoType::Empty; 100 * 4]).unwrap();
+        assert!(parse_board(&json, 100).is_err());
+        let json = serde_json::to_string(&vec![MinoType::Empty; MAX_BOARD_WIDTH * 4]).unwrap();
+        assert!(parse_board(&json, MAX_BOARD_WIDTH).is_ok());
+    }
+
+    #[test]
+    fn queues_are_read_from_letters_or_json() {
+        let queue = [MinoType::T, MinoType::I, MinoType::O];
+        assert_eq!(parse_queue("tIo").unwrap(), queue);
+        assert_eq!(parse_queue("[6, 4, 2]").unwrap(), queue);
+        assert_eq!(parse_queue("").unwrap(), []);
+        assert!(parse_queue("TX").is_err());
+    }
+
+    #[test]
+    fn a_board_without_a_clear_still_has_a_best_placement() {
+        //nothing an o can do on an empty board clears a line
+        let empty = board(&[]);
+        let analysis = analyze_position(&empty, 10, MinoType::O, &[], 0, 0);
+        assert!(!analysis.best_placements.is_empty());
+        for suggested in &analysis.best_placements {
+            assert_eq!(suggested.placement.shape, MinoType::O);
+            assert_eq!(suggested.attack_potential, 0);
+            assert!(suggested.fumen.is_some());
+        }
+        assert!(analysis.fumen.is_some());
+    }
+}
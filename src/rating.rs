@@ -0,0 +1,94 @@
+use std::f64::consts::{LN_10, PI};
+
+///rating deviation assumed for estimated ratings, the lowest a settled player can have
+const ESTIMATED_RD: f64 = 60.0;
+
+///offense and defense metrics in the form stat sites show them
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TradeStats {
+    pub ds_per_second: f64,
+    pub ds_per_piece: f64,
+    pub cheese_index: f64,
+    pub garbage_efficiency: f64,
+    pub area: f64,
+    pub estimated_glicko: f64,
+    pub estimated_tr: f64,
+}
+
+impl TradeStats {
+    ///`vs` is (attack + garbage cleared) per second times 100
+    pub fn new(apm: f64, pps: f64, vs: f64) -> Self {
+        let app = apm / (pps * 60.0);
+        let ds_per_second = vs / 100.0 - apm / 60.0;
+        let ds_per_piece = ds_per_second / pps;
+        let vs_apm = vs / apm;
+        let cheese_index = ds_per_piece * 150.0 + (vs_apm - 2.0) * 50.0 + (0.6 - app) * 125.0;
+        let garbage_efficiency = app * ds_per_second / pps * 2.0;
+        let area = apm
+            + pps * 45.0
+            + vs * 0.444
+            + app * 185.0
+            + ds_per_second * 175.0
+            + ds_per_piece * 450.0
+            + garbage_efficiency * 315.0;
+        let estimated_glicko = estimate_glicko(pps, app, ds_per_piece, vs_apm);
+        Self {
+            ds_per_second,
+            ds_per_piece,
+            cheese_index,
+            garbage_efficiency,
+            area,
+            estimated_glicko,
+            estimated_tr: glicko_to_tr(estimated_glicko),
+        }
+    }
+}
+
+///cubic fit of glicko against a weighted mix of speed, attack and defense
+fn estimate_glicko(pps: f64, app: f64, ds_per_piece: f64, vs_apm: f64) -> f64 {
+    let x = pps * (150.0 + (vs_apm - 1.66) * 35.0) + app * 290.0 + ds_per_piece * 700.0;
+    0.000013 * x.powi(3) - 0.0196 * x.powi(2) + 12.645 * x - 1005.4
+}
+
+///tetra league rating for a glicko, the same conversion the game uses
+fn glicko_to_tr(glicko: f64) -> f64 {
+    let spread = (3.0 * LN_10.powi(2) * ESTIMATED_RD.powi(2)
+        + 2500.0 * (64.0 * PI.powi(2) + 147.0 * LN_10.powi(2)))
+    .sqrt();
+    25000.0 / (1.0 + 10f64.powf((1500.0 - glicko) * PI / spread))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-6,
+            "{} isn't {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn trade_stats_match_the_stat_sheet() {
+        //60 apm at 2 pps with 120 vs is 0.5 app and 0.2 ds/second
+        let trade = TradeStats::new(60.0, 2.0, 120.0);
+        assert_close(trade.ds_per_second, 0.2);
+        assert_close(trade.ds_per_piece, 0.1);
+        assert_close(trade.cheese_index, 27.5);
+        assert_close(trade.garbage_efficiency, 0.1);
+        assert_close(trade.area, 407.28);
+        assert_close(trade.estimated_glicko, 2151.1547999360005);
+        assert_close(trade.estimated_tr, 23082.04447348158);
+    }
+
+    #[test]
+    fn tr_is_centered_on_a_1500_glicko() {
+        assert_close(glicko_to_tr(1500.0), 12500.0);
+        //the curve is symmetric around the center
+        assert_close(glicko_to_tr(1000.0) + glicko_to_tr(2000.0), 25000.0);
+        assert_close(glicko_to_tr(2000.0), 21776.306531627033);
+    }
+}
@@ -0,0 +1,165 @@
+use crate::engine::{Engine, Lock, Randomizer};
+use crate::replay_response::{PlacementStats, DEFAULT_BOARD_HEIGHT, DEFAULT_BOARD_WIDTH};
+use std::collections::VecDeque;
+
+#[derive(Debug)]
+pub struct ReplayError(pub String);
+impl std::fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("Invalid replay: {}", self.0))?;
+        Ok(())
+    }
+}
+impl std::error::Error for ReplayError {}
+
+///how incoming garbage behaves in a game
+#[derive(Debug, Clone, Copy)]
+pub struct GarbageRules {
+    ///frames garbage waits before it can rise
+    pub delay: f64,
+    ///most lines that rise after a single placement
+    pub cap: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Incoming {
+    lines: usize,
+    column: usize,
+    frame: f64,
+}
+
+///replays one player's inputs on an engine and writes down every placement like a replay response would
+pub struct Recorder<R: Randomizer> {
+    engine: Engine<R>,
+    rules: GarbageRules,
+    attack: fn(&Lock) -> usize,
+    incoming: VecDeque<Incoming>,
+    received: Vec<usize>,
+    ///garbage that rose outside of a placement, counted as tanked by the next one
+    risen: Vec<usize>,
+    keypresses: usize,
+    last_lock: f64,
+    placements: Vec<PlacementStats>,
+}
+
+impl<R: Randomizer> Recorder<R> {
+    pub fn new(engine: Engine<R>, rules: GarbageRules, attack: fn(&Lock) -> usize) -> Self {
+        Self {
+            engine,
+            rules,
+            attack,
+            incoming: VecDeque::new(),
+            received: Vec::new(),
+            risen: Vec::new(),
+            keypresses: 0,
+            last_lock: 0.0,
+            placements: Vec::new(),
+        }
+    }
+
+    pub fn engine(&mut self) -> &mut Engine<R> {
+        &mut self.engine
+    }
+
+    pub fn key_pressed(&mut self) {
+        self.keypresses += 1;
+    }
+
+    pub fn receive(&mut self, lines: usize, column: usize, frame: f64) {
+        if lines == 0 {
+            return;
+        }
+        self.received.push(lines);
+        self.incoming.push_back(Incoming {
+            lines,
+            column,
+            frame,
+        });
+    }
+
+    ///garbage that rises straight away, for games whose replays only record garbage once it is on the board
+    pub fn rise(&mut self, lines: usize, column: usize) {
+        if lines == 0 {
+            return;
+        }
+        self.received.push(lines);
+        self.risen.push(lines);
+        self.engine.add_garbage(lines, column);
+    }
+
+    pub fn hard_drop(&mut self, frame: f64) {
+        let Some(lock) = self.engine.hard_drop() else {
+            return;
+        };
+        let attack = if lock.lines_cleared > 0 {
+            (self.attack)(&lock)
+        } else {
+            0
+        };
+
+        //attack cancels the oldest incoming garbage first
+        let mut remaining = attack;
+        while remaining > 0 {
+            let Some(incoming) = self.incoming.front_mut() else {
+                break;
+            };
+            let cancelled = remaining.min(incoming.lines);
+            incoming.lines -= cancelled;
+            remaining -= cancelled;
+            if incoming.lines == 0 {
+                self.incoming.pop_front();
+            }
+        }
+
+        let mut tanked = std::mem::take(&mut self.risen);
+        if lock.lines_cleared == 0 {
+            let mut room = self.rules.cap;
+            while room > 0 {
+                let Some(incoming) = self
+                    .incoming
+                    .front_mut()
+                    .filter(|incoming| incoming.frame + self.rules.delay <= frame)
+                else {
+                    break;
+                };
+                let lines = room.min(incoming.lines);
+                let column = incoming.column;
+                incoming.lines -= lines;
+                room -= lines;
+                if incoming.lines == 0 {
+                    self.incoming.pop_front();
+                }
+                self.engine.add_garbage(lines, column);
+                tanked.push(lines);
+            }
+        }
+
+        self.placements.push(PlacementStats {
+            shape: lock.shape,
+            lines_cleared: lock.lines_cleared,
+            garbage_cleared: lock.garbage_cleared,
+            keypresses: std::mem::take(&mut self.keypresses),
+            attack: if attack > 0 { vec![attack] } else { Vec::new() },
+            clear_type: lock.clear_type,
+            combo: lock.combo.map_or(0, |combo| combo + 1),
+            btb_chain: lock.btb.map_or(0, |btb| btb + 1),
+            btb_clear: lock.btb_clear,
+            frame_delay: frame - self.last_lock,
+            attack_received: std::mem::take(&mut self.received),
+            attack_tanked: tanked,
+            board: self.engine.board(),
+            queue: self.engine.queue(),
+            board_width: DEFAULT_BOARD_WIDTH,
+            board_height: DEFAULT_BOARD_HEIGHT,
+        });
+        self.last_lock = frame;
+    }
+
+    pub fn topped_out(&self) -> bool {
+        self.engine.topped_out()
+    }
+
+    pub fn finish(self) -> Vec<PlacementStats> {
+        self.placements
+    }
+}
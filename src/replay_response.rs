@@ -1,9 +1,23 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_repr::{Deserialize_repr, Serialize_repr};
 
 pub type Board = Vec<MinoType>;
 
-#[derive(Debug, Serialize, Deserialize)]
+pub const DEFAULT_BOARD_WIDTH: usize = 10;
+pub const DEFAULT_BOARD_HEIGHT: usize = 40;
+///widest board a packed row can hold
+pub const MAX_BOARD_WIDTH: usize = 64;
+
+fn default_board_width() -> usize {
+    DEFAULT_BOARD_WIDTH
+}
+
+fn default_board_height() -> usize {
+    DEFAULT_BOARD_HEIGHT
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct PlacementStats {
     pub shape: MinoType,
     #[serde(rename = "linesCleared")]
@@ -21,15 +35,37 @@ pub struct PlacementStats {
     pub btb_clear: bool,
     #[serde(rename = "frameDelay")]
     pub frame_delay: f64,
-    #[serde(rename = "attackRecieved")]
+    ///also read as `attackReceived`
+    #[serde(rename = "attackRecieved", alias = "attackReceived")]
     pub attack_received: Vec<usize>,
     #[serde(rename = "attackTanked")]
     pub attack_tanked: Vec<usize>,
     pub board: Board,
     pub queue: Vec<MinoType>,
+    #[serde(rename = "boardWidth", default = "default_board_width")]
+    pub board_width: usize,
+    #[serde(rename = "boardHeight", default = "default_board_height")]
+    pub board_height: usize,
+}
+
+impl PlacementStats {
+    ///make sure the board has as many cells as its dimensions say
+    pub fn validate(&self) -> Result<(), BoardSizeError> {
+        if self.board_width == 0
+            || self.board_width > MAX_BOARD_WIDTH
+            || self.board.len() != self.board_width * self.board_height
+        {
+            return Err(BoardSizeError {
+                width: self.board_width,
+                height: self.board_height,
+                cells: self.board.len(),
+            });
+        }
+        Ok(())
+    }
 }
 
-#[derive(Hash, Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[derive(Hash, Debug, Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
 pub enum ClearType {
     #[serde(rename = "NONE")]
@@ -81,7 +117,7 @@ impl ClearType {
 }
 
 #[derive(Debug)]
-pub struct OutOfBoundsError(u8);
+pub struct OutOfBoundsError(pub u8);
 impl std::fmt::Display for OutOfBoundsError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_fmt(format_args!(
@@ -93,6 +129,23 @@ impl std::fmt::Display for OutOfBoundsError {
 }
 impl std::error::Error for OutOfBoundsError {}
 
+#[derive(Debug)]
+pub struct BoardSizeError {
+    pub width: usize,
+    pub height: usize,
+    pub cells: usize,
+}
+impl std::fmt::Display for BoardSizeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!(
+            "Board of {} cells does not match its {}x{} dimensions",
+            self.cells, self.width, self.height
+        ))?;
+        Ok(())
+    }
+}
+impl std::error::Error for BoardSizeError {}
+
 impl std::convert::TryFrom<u8> for ClearType {
     type Error = OutOfBoundsError;
 
@@ -133,6 +186,22 @@ pub enum MinoType {
     Empty,
 }
 
+//pieces are written as their number, unlike clear types which are written as names
+impl JsonSchema for MinoType {
+    fn schema_name() -> std::borrow::Cow<'static, str> {
+        "MinoType".into()
+    }
+
+    fn json_schema(_: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        schemars::json_schema!({
+            "description": "0 Z, 1 L, 2 O, 3 S, 4 I, 5 J, 6 T, 7 garbage, 8 empty",
+            "type": "integer",
+            "minimum": 0,
+            "maximum": 8
+        })
+    }
+}
+
 impl std::convert::TryFrom<u8> for MinoType {
     type Error = OutOfBoundsError;
 
@@ -0,0 +1,261 @@
+use crate::distribution::{Distribution, FRAME_DELAY_BUCKETS};
+use crate::fumen;
+use crate::match_report::GameInput;
+use crate::placement_stats::{round_delay, CumulativePlacementStats, PlacementFeatures};
+use crate::replay_response::{MinoType, PlacementStats};
+use serde::Serialize;
+
+///attack potential at which a board could have spiked, same as a spikable board
+const SPIKE_POTENTIAL: usize = 9;
+///standard deviations above the game's mean delay for a placement to be slow
+const SLOW_SDS: f64 = 3.0;
+
+///a placement with what the game loop worked out for it and anything worth pointing out, including the perfect
+///clear found on its board
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AnnotatedPlacement {
+    ///index of the placement in its game
+    pub placement: usize,
+    ///seconds into the game once the piece was placed
+    pub time: f64,
+    pub shape: MinoType,
+    pub attack: usize,
+    #[serde(flatten)]
+    pub features: PlacementFeatures,
+    pub tags: Vec<String>,
+    ///the board before and after the placement, only for tagged placements on 10 wide boards
+    pub fumen: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GameReview {
+    pub game_id: Option<String>,
+    pub placements: Vec<AnnotatedPlacement>,
+}
+
+///attack sent by the pieces the solver could see after a placement
+pub(crate) fn attack_sent_after(game: &[PlacementStats], i: usize) -> usize {
+    //the first piece in the queue is the hold and the solver looks at 8 pieces
+    let pieces = game[i].queue.len().min(8).saturating_sub(1);
+    game.iter()
+        .skip(i + 1)
+        .take(pieces)
+        .map(|placement| placement.attack.iter().sum::<usize>())
+        .sum()
+}
+
+///a board that could have spiked where less than half of what it could send was sent after it
+pub(crate) fn missed_spike(potential: usize, sent: usize) -> bool {
+    potential >= SPIKE_POTENTIAL && sent * 2 < potential
+}
+
+///every placement of a game with its tags
+pub fn review_game(game: &[PlacementStats]) -> Vec<AnnotatedPlacement> {
+    let stats = CumulativePlacementStats::from(game);
+    let delays = Distribution::new(stats.delays.iter().copied(), FRAME_DELAY_BUCKETS);
+    let slow_delay = delays.map_or(f64::INFINITY, |delays| delays.mean + SLOW_SDS * delays.sd);
+
+    let mut frames = 0.0;
+    let mut missing_spike = false;
+    let mut review = Vec::new();
+    for (i, (placement, features)) in game.iter().zip(stats.placement_features).enumerate() {
+        frames += round_delay(placement.frame_delay);
+        let mut tags = Vec::new();
+
+        //only the first board of a run that could have spiked gets tagged
+        let potential = features.attack_potential.unwrap_or(0);
+        let sent = attack_sent_after(game, i);
+        let missed = missed_spike(potential, sent);
+        if missed && !missing_spike {
+            tags.push(format!(
+                "missed spike (potential {}, sent {})",
+                potential, sent
+            ));
+        }
+        missing_spike = missed;
+
+        if features.wellshift {
+            tags.push("wellshift during BTB".to_string());
+        }
+        if features.pc_solution.as_ref().is_some_and(|pc| !pc.taken) {
+            tags.push("missed perfect clear".to_string());
+        }
+        if features.cheese_cleared {
+            tags.push("cheese cleared".to_string());
+        }
+        if round_delay(placement.frame_delay) > slow_delay {
+            tags.push(format!("slow placement ({}σ)", SLOW_SDS));
+        }
+
+        let fumen = (!tags.is_empty() && placement.board_width == fumen::FUMEN_WIDTH)
+            .then(|| {
+                //the first board, or one after the board changed size, starts from empty
+                let previous = i
+                    .checked_sub(1)
+                    .map(|previous| &game[previous].board)
+                    .filter(|previous| previous.len() == placement.board.len())
+                    .map_or_else(
+                        || vec![MinoType::Empty; placement.board.len()],
+                        Clone::clone,
+                    );
+                fumen::encode(&[previous, placement.board.clone()])
+            })
+            .flatten();
+
+        review.push(AnnotatedPlacement {
+            placement: i,
+            time: frames / 60.0,
+            shape: placement.shape,
+            attack: placement.attack.iter().sum(),
+            features,
+            tags,
+            fumen,
+        });
+    }
+    review
+}
+
+pub fn review_games(games: &[GameInput]) -> Vec<GameReview> {
+    games
+        .iter()
+        .map(|game| GameReview {
+            game_id: game.meta().map(|meta| meta.game_id.clone()),
+            placements: review_game(game.placements()),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::position::parse_queue;
+    use crate::replay_response::ClearType;
+    use crate::test_support::{board, board_of_width, placement};
+
+    fn tags(review: &[AnnotatedPlacement]) -> Vec<Vec<&str>> {
+        review
+            .iter()
+            .map(|placement| placement.tags.iter().map(String::as_str).collect())
+            .collect()
+    }
+
+    #[test]
+    fn clearing_cheese_is_tagged_with_the_boards_around_it() {
+        let game = [
+            PlacementStats {
+                board: board(&["#####.####", "######.###"]),
+                ..placement()
+            },
+            PlacementStats {
+                lines_cleared: 1,
+                garbage_cleared: 1,
+                clear_type: ClearType::Single,
+                board: board(&["######.###"]),
+                ..placement()
+            },
+        ];
+        let review = review_game(&game);
+        assert_eq!(tags(&review), [vec![], vec!["cheese cleared"]]);
+        assert_eq!(review[0].fumen, None);
+        let fumen = review[1].fumen.as_deref().expect("tagged 10 wide boards");
+        assert_eq!(
+            fumen::decode(fumen).unwrap(), //encoded above
+            [game[0].board.clone(), game[1].board.clone()]
+        );
+    }
+
+    #[test]
+    fn boards_that_cant_be_a_fumen_are_tagged_without_one() {
+        let game = [
+            PlacementStats {
+                board: board_of_width(12, &["######.#####", "#######.####"]),
+                board_width: 12,
+                ..placement()
+            },
+            PlacementStats {
+                lines_cleared: 1,
+                garbage_cleared: 1,
+                clear_type: ClearType::Single,
+                board: board_of_width(12, &["#######.####"]),
+                board_width: 12,
+                ..placement()
+            },
+        ];
+        let review = review_game(&game);
+        assert_eq!(review[1].tags, ["cheese cleared"]);
+        assert_eq!(review[1].fumen, None);
+    }
+
+    #[test]
+    fn moving_the_well_during_btb_is_a_wellshift() {
+        //wells only count once the lowest column is over 4 high
+        let stack = |top: &'static str| {
+            let mut rows = vec![top; 3];
+            rows.extend(["LLLLLLLLLL"; 5]);
+            board(&rows)
+        };
+        let game = [
+            PlacementStats {
+                shape: MinoType::I,
+                lines_cleared: 4,
+                clear_type: ClearType::Quad,
+                board: stack("LLLLLLLLL."),
+                ..placement()
+            },
+            PlacementStats {
+                board: stack("LLLLLLLLL."),
+                ..placement()
+            },
+            PlacementStats {
+                board: stack(".LLLLLLLLL"),
+                ..placement()
+            },
+        ];
+        let review = review_game(&game);
+        assert_eq!(
+            tags(&review),
+            [vec![], vec![], vec!["wellshift during BTB"]]
+        );
+    }
+
+    #[test]
+    fn placements_three_deviations_slower_than_the_mean_are_slow() {
+        let mut game: Vec<_> = (0..20).map(|_| placement()).collect();
+        game.push(PlacementStats {
+            frame_delay: 1000.0,
+            ..placement()
+        });
+        let review = review_game(&game);
+        let slow: Vec<_> = review
+            .iter()
+            .filter(|placement| !placement.tags.is_empty())
+            .map(|placement| (placement.placement, placement.tags.clone()))
+            .collect();
+        assert_eq!(slow, [(20, vec!["slow placement (3σ)".to_string()])]);
+    }
+
+    #[test]
+    fn a_perfect_clear_left_on_the_board_is_missed() {
+        //a 4x4 box the o pieces after the held t can fill
+        let game: Vec<_> = (0..5)
+            .map(|_| PlacementStats {
+                board: board(&["LLLLLL...."; 4]),
+                queue: parse_queue("OTOOO").unwrap(), //valid queue
+                ..placement()
+            })
+            .collect();
+        let review = review_game(&game);
+        assert_eq!(review[0].tags, ["missed perfect clear"]);
+        //the placements the solution would have taken are covered by the first one
+        assert!(review[1..].iter().all(|p| p.tags.is_empty()));
+    }
+
+    #[test]
+    fn spikes_are_missed_when_less_than_half_is_sent() {
+        assert!(missed_spike(SPIKE_POTENTIAL, 4));
+        assert!(!missed_spike(SPIKE_POTENTIAL, 5));
+        assert!(!missed_spike(SPIKE_POTENTIAL - 1, 0));
+    }
+}
@@ -0,0 +1,253 @@
+use crate::bitboard::BitBoard;
+use crate::board_analyzer::{get_height, matches_pattern};
+use crate::engine::cells;
+use crate::replay_response::{MinoType, OutOfBoundsError};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Hash, Debug, Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Setup {
+    #[serde(rename = "STSD")]
+    Stsd,
+    #[serde(rename = "TST_TOWER")]
+    TstTower,
+    #[serde(rename = "FRACTAL")]
+    Fractal,
+    #[serde(rename = "IMPERIAL_CROSS")]
+    ImperialCross,
+    #[serde(rename = "DT")]
+    Dt,
+    #[serde(rename = "CSPIN")]
+    Cspin,
+}
+
+///amount of setups, one past the last variant
+pub const SETUP_COUNT: usize = Setup::Cspin as usize + 1;
+
+impl std::convert::TryFrom<u8> for Setup {
+    type Error = OutOfBoundsError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Setup::Stsd),
+            1 => Ok(Setup::TstTower),
+            2 => Ok(Setup::Fractal),
+            3 => Ok(Setup::ImperialCross),
+            4 => Ok(Setup::Dt),
+            5 => Ok(Setup::Cspin),
+            _ => Err(OutOfBoundsError(value)),
+        }
+    }
+}
+
+struct SetupPattern {
+    setup: Setup,
+    shape: &'static [&'static str],
+}
+
+//left handed versions written like the opener catalog, mirrored boards are matched as well
+#[rustfmt::skip]
+const SETUPS: [SetupPattern; SETUP_COUNT] = [
+    SetupPattern {
+        setup: Setup::Stsd,
+        shape: &[
+            "?#..",
+            "#...",
+            "..##",
+            "#.##",
+        ],
+    },
+    SetupPattern {
+        setup: Setup::TstTower,
+        shape: &[
+            "#.??",
+            "#.??",
+            "#.##",
+            "..##",
+            "#.##",
+        ],
+    },
+    SetupPattern {
+        setup: Setup::Fractal,
+        shape: &[
+            "##...",
+            "#....",
+            "##.##",
+            "#...#",
+            "##.##",
+        ],
+    },
+    SetupPattern {
+        setup: Setup::ImperialCross,
+        shape: &[
+            "??.??",
+            "##.##",
+            "#...#",
+            "##.##",
+        ],
+    },
+    SetupPattern {
+        setup: Setup::Dt,
+        shape: &[
+            "##???",
+            "#...?",
+            "##.##",
+            "##.##",
+            "#..##",
+        ],
+    },
+    SetupPattern {
+        setup: Setup::Cspin,
+        shape: &[
+            "?##.",
+            "#...",
+            "#..#",
+            "##.#",
+        ],
+    },
+];
+
+///where a setup was found, `x` and `y` are the bottom left corner of its pattern
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SetupMatch {
+    pub setup: Setup,
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl SetupMatch {
+    fn contains(&self, (x, y): (usize, usize)) -> bool {
+        (self.x..self.x + self.width).contains(&x) && (self.y..self.y + self.height).contains(&y)
+    }
+}
+
+///finds every setup on the board with every place it is in, ordered like `Setup`
+pub fn detect_setups(board: &BitBoard) -> Vec<SetupMatch> {
+    let height = get_height(board);
+    let width = board.width();
+    let board_height = board.height();
+    let mut matches = Vec::new();
+    for pattern in SETUPS.iter() {
+        let pattern_width = pattern.shape[0].len();
+        let pattern_height = pattern.shape.len();
+        for x in 0..=width.saturating_sub(pattern_width) {
+            for y in 0..=height.min(board_height.saturating_sub(pattern_height)) {
+                if matches_pattern(board, pattern.shape, x, y, false)
+                    || matches_pattern(board, pattern.shape, x, y, true)
+                {
+                    matches.push(SetupMatch {
+                        setup: pattern.setup,
+                        x,
+                        y,
+                        width: pattern_width,
+                        height: pattern_height,
+                    });
+                }
+            }
+        }
+    }
+    matches
+}
+
+///cells of a t piece placed on `before` that clears `lines` rows and leaves `after`, once `risen` rows of
+///garbage have been pushed up from below
+fn find_t_cells(
+    before: &BitBoard,
+    after: &BitBoard,
+    lines: usize,
+    risen: usize,
+) -> Option<[(usize, usize); 4]> {
+    let (width, height) = (before.width(), before.height());
+    if after.width() != width || after.height() != height || risen > height {
+        return None;
+    }
+    let rows: Vec<u64> = (0..height).map(|y| before.row(y)).collect();
+    for rotation in 0..4 {
+        for origin_x in 0..width as i32 {
+            for origin_y in 0..height as i32 {
+                let placed =
+                    cells(MinoType::T, rotation).map(|(x, y)| (origin_x + x, origin_y + y));
+                let fits = placed.iter().all(|&(x, y)| {
+                    (0..width as i32).contains(&x)
+                        && (0..height as i32).contains(&y)
+                        && !before.is_filled(x as usize, y as usize)
+                });
+                if !fits {
+                    continue;
+                }
+                let mut next = rows.clone();
+                for &(x, y) in placed.iter() {
+                    next[y as usize] |= 1 << x;
+                }
+                next.retain(|&row| row != before.full_row());
+                if height - next.len() != lines {
+                    continue;
+                }
+                next.resize(height, 0);
+                if (0..height - risen).all(|y| after.row(y + risen) == next[y]) {
+                    return Some(placed.map(|(x, y)| (x as usize, y as usize)));
+                }
+            }
+        }
+    }
+    None
+}
+
+///the setup a t spin placed on `before` cashed in, the one whose pattern the t piece was put in
+pub fn cashed_setup(
+    setups: &[SetupMatch],
+    before: &BitBoard,
+    after: &BitBoard,
+    lines: usize,
+    risen: usize,
+) -> Option<Setup> {
+    //boards may be recorded before or after the garbage rises
+    let t_cells = find_t_cells(before, after, lines, risen)
+        .or_else(|| find_t_cells(before, after, lines, 0))?;
+    setups
+        .iter()
+        .find(|setup| t_cells.iter().all(|&cell| setup.contains(cell)))
+        .map(|setup| setup.setup)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solver::{MoveOrientation, SolverMove};
+    use crate::test_support::board;
+
+    #[test]
+    fn cash_goes_to_the_setup_the_t_was_placed_in() {
+        //an stsd sits on top of an imperial cross, and the t spin double goes into the cross
+        let before = board(&[
+            ".#........",
+            "#.........",
+            "..###.....",
+            "#.#####.##",
+            "#######.##",
+            "######...#",
+            "#######.##",
+        ]);
+        let mut after = before.clone();
+        SolverMove {
+            shape: MinoType::T,
+            orientation: MoveOrientation::South,
+            x: 6,
+            y: 0,
+        }
+        .place_on(&mut after, 10);
+        let (before, after) = (BitBoard::new(&before, 10), BitBoard::new(&after, 10));
+
+        let setups = detect_setups(&before);
+        let kinds: Vec<_> = setups.iter().map(|setup| setup.setup).collect();
+        assert!(kinds.contains(&Setup::Stsd));
+        assert!(kinds.contains(&Setup::ImperialCross));
+        assert_eq!(
+            cashed_setup(&setups, &before, &after, 2, 0),
+            Some(Setup::ImperialCross)
+        );
+    }
+}
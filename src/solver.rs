@@ -1,56 +1,218 @@
+use crate::bitboard::BitBoard;
+use crate::board_analyzer::get_height;
+use crate::engine::cells;
 use crate::replay_response::{Board, MinoType};
+use serde::{Deserialize, Serialize};
 use std::{collections::VecDeque, fmt::Display};
 
 use crate::attack::get_indexed_attack;
 use bitris::prelude::*;
 
-///parse replay response types into a bitris node and queue
+///where pieces spawn, centered on the board just above the visible half
+pub(crate) fn spawn_position(width: usize, height: usize) -> CcPosition {
+    cc(((width - 1) / 2) as i32, (height / 2 + 1) as i32)
+}
+
+pub(crate) fn mino_to_shape(mino: MinoType) -> Option<Shape> {
+    use Shape::*;
+    match mino {
+        MinoType::Z => Some(Z),
+        MinoType::L => Some(L),
+        MinoType::O => Some(O),
+        MinoType::S => Some(S),
+        MinoType::I => Some(I),
+        MinoType::J => Some(J),
+        MinoType::T => Some(T),
+        _ => None,
+    }
+}
+
+fn shape_to_mino(shape: Shape) -> MinoType {
+    match shape {
+        Shape::Z => MinoType::Z,
+        Shape::L => MinoType::L,
+        Shape::O => MinoType::O,
+        Shape::S => MinoType::S,
+        Shape::I => MinoType::I,
+        Shape::J => MinoType::J,
+        Shape::T => MinoType::T,
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MoveOrientation {
+    North,
+    East,
+    South,
+    West,
+}
+
+///a placement found by the solver, `x` and `y` are the bottom left corner of the piece
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SolverMove {
+    pub shape: MinoType,
+    pub orientation: MoveOrientation,
+    pub x: i32,
+    pub y: i32,
+}
+
+impl From<&BlPlacement> for SolverMove {
+    fn from(placement: &BlPlacement) -> Self {
+        Self {
+            shape: shape_to_mino(placement.piece.shape),
+            orientation: match placement.piece.orientation {
+                Orientation::North => MoveOrientation::North,
+                Orientation::East => MoveOrientation::East,
+                Orientation::South => MoveOrientation::South,
+                Orientation::West => MoveOrientation::West,
+            },
+            x: placement.position.lx,
+            y: placement.position.by,
+        }
+    }
+}
+
+impl SolverMove {
+    ///puts the piece on a board stored top row first and clears the lines it fills
+    pub fn place_on(&self, board: &mut Board, width: usize) {
+        let height = board.len() / width;
+        let rotation = self.orientation as usize;
+        let cells = cells(self.shape, rotation);
+        let left = cells.iter().map(|cell| cell.0).min().unwrap(); //pieces have 4 cells
+        let bottom = cells.iter().map(|cell| cell.1).min().unwrap(); //pieces have 4 cells
+        for (x, y) in cells {
+            let (x, y) = (self.x + x - left, self.y + y - bottom);
+            if (0..width as i32).contains(&x) && (0..height as i32).contains(&y) {
+                board[x as usize + (height - 1 - y as usize) * width] = self.shape;
+            }
+        }
+        let rows: Vec<_> = board
+            .chunks(width)
+            .filter(|row| row.contains(&MinoType::Empty))
+            .flatten()
+            .copied()
+            .collect();
+        let cleared = board.len() - rows.len();
+        board[..cleared].fill(MinoType::Empty);
+        board[cleared..].copy_from_slice(&rows);
+    }
+}
+
+///parse replay response types into a bitris node and queue, none if the board is too big or there are no pieces
 fn parse_replay_args(
-    board: &Board,
+    board: &BitBoard,
     btb: usize,
     combo: usize,
     queue: &[MinoType],
-) -> (Node, VecDeque<Shape>) {
-    let mut board64 = Board64::blank();
-    for y in 0..40 {
-        for x in 0..10 {
-            if board[(39 - y) * 10 + x] != MinoType::Empty {
-                board64.set_at(Location {
-                    x: x as i32,
-                    y: y as i32,
-                });
-            }
-        }
-    }
-    let mut vec_queue = VecDeque::new();
-    for &p in queue.iter().take(8) {
-        use Shape::*;
-        vec_queue.push_back(match p {
-            MinoType::Z => Z,
-            MinoType::L => L,
-            MinoType::O => O,
-            MinoType::S => S,
-            MinoType::I => I,
-            MinoType::J => J,
-            MinoType::T => T,
-            _ => continue,
-        })
-    }
-    let hold = vec_queue.pop_front().unwrap();
+) -> Option<(Node, VecDeque<Shape>)> {
+    let board64 = board.to_board64()?;
+    let mut vec_queue: VecDeque<_> = queue
+        .iter()
+        .take(8)
+        .filter_map(|&p| mino_to_shape(p))
+        .collect();
+    //nothing to place without a hold or current piece
+    let hold = vec_queue.pop_front()?;
     let node = Node {
         board: board64,
+        spawn: spawn_position(board.width(), board.height()),
         hold,
         btb,
         combo,
         attack: 0,
     };
-    (node, vec_queue)
+    Some((node, vec_queue))
 }
 
-///dfs to get atk and def
-pub fn solve_state(board: &Board, btb: usize, combo: usize, queue: &[MinoType]) -> (usize, usize) {
-    let (node, mut queue) = parse_replay_args(board, btb, combo, queue);
-    dfs(node, &mut queue)
+///dfs to get atk and def, none if the board is too big for the solver or there are no pieces
+pub fn solve_state(
+    board: &BitBoard,
+    btb: usize,
+    combo: usize,
+    queue: &[MinoType],
+) -> Option<(usize, usize)> {
+    let (node, mut queue) = parse_replay_args(board, btb, combo, queue)?;
+    Some(dfs(node, &mut queue))
+}
+
+///every placement of the current or hold piece with the atk and def the rest of the queue reaches after it, best
+///attack first
+///
+///placements that don't clear are ranked too, a position without a clear in it still has a best move
+pub fn best_placements(
+    board: &BitBoard,
+    btb: usize,
+    combo: usize,
+    queue: &[MinoType],
+) -> Option<Vec<(SolverMove, usize, usize)>> {
+    let (node, mut queue) = parse_replay_args(board, btb, combo, queue)?;
+    let mut children = Vec::new();
+    match queue.pop_front() {
+        Some(current) => {
+            children.extend(node.get_placements(current, node.hold));
+            if current != node.hold {
+                children.extend(node.get_placements(node.hold, current));
+            }
+        }
+        //only the hold piece is left
+        None => children.extend(node.get_placements(node.hold, node.hold)),
+    }
+    let mut placements: Vec<_> = children
+        .into_iter()
+        .map(|(placement, child, _)| {
+            let (atk, def) = dfs(child, &mut queue);
+            (SolverMove::from(&placement), atk, def)
+        })
+        .collect();
+    placements.sort_by_key(|&(_, atk, def)| std::cmp::Reverse((atk, def)));
+    Some(placements)
+}
+
+///estimate how close a board is to topping out, 0 is a safe board and 1 is a guaranteed top out
+///
+///every piece in the queue is checked against the spawn position after the incoming garbage is
+///raised into the board, a piece that can't spawn is a block out and a piece that can't drop
+///below the visible matrix is a lock out
+pub fn get_death_risk(board: &BitBoard, queue: &[MinoType], incoming: usize) -> f64 {
+    let visible = board.height() / 2;
+    let spawn = spawn_position(board.width(), board.height());
+    let fall_heights: Vec<_> = match board.to_board64() {
+        Some(board64) => queue
+            .iter()
+            .take(8)
+            .filter_map(|&p| mino_to_shape(p))
+            .map(|shape| get_fall_height(&board64, shape, spawn))
+            .collect(),
+        //boards the solver can't hold only get an estimate from the stack height
+        None => vec![(visible + 2).saturating_sub(get_height(board))],
+    };
+    let mut risk: f64 = 0.0;
+    for fall_height in fall_heights {
+        //free rows below spawn once the garbage has been raised
+        let drop = match fall_height.checked_sub(incoming + 1) {
+            Some(drop) => drop,
+            None => return 1.0, //block out
+        };
+        if drop <= 1 {
+            return 1.0; //lock out
+        }
+        risk = risk.max(1.0 - (drop - 1) as f64 / visible as f64);
+    }
+    risk.clamp(0.0, 1.0)
+}
+
+///amount of positions a piece can occupy when dropped straight down from spawn
+fn get_fall_height(board: &Board64, shape: Shape, spawn: CcPosition) -> usize {
+    let mut height = 0;
+    let mut spawn = Piece::new(shape, Orientation::North)
+        .with(spawn)
+        .to_bl_placement();
+    while spawn.is_in_free_space(board) {
+        height += 1;
+        spawn += Offset { dx: 0, dy: -1 };
+    }
+    height
 }
 
 //we do tspin check with immobile, hopefully it is sufficient
@@ -81,6 +243,7 @@ fn is_immobile(board: &Board64, placement: &BlPlacement) -> bool {
 #[derive(Clone)]
 struct Node {
     board: Board64,
+    spawn: CcPosition,
     hold: Shape,
     btb: usize,
     combo: usize,
@@ -99,19 +262,21 @@ impl Display for Node {
 
 impl Node {
     fn get_fall_height(&self, shape: Shape) -> usize {
-        let mut defence = 0;
-        let mut spawn = Piece::new(shape, Orientation::North)
-            .with(cc(4, 21))
-            .to_bl_placement();
-        while spawn.is_in_free_space(&self.board) {
-            defence += 1;
-            spawn += Offset { dx: 0, dy: -1 };
-        }
-        defence
+        get_fall_height(&self.board, shape, self.spawn)
+    }
+    ///the search only follows placements that clear lines
+    fn get_children(&self, shape: Shape, next_hold: Shape) -> Vec<(BlPlacement, Self)> {
+        self.get_placements(shape, next_hold)
+            .into_iter()
+            .filter(|&(_, _, lines_cleared)| lines_cleared > 0)
+            .map(|(placement, node, _)| (placement, node))
+            .collect()
     }
-    fn get_children(&self, shape: Shape, next_hold: Shape) -> Vec<Self> {
+
+    ///every placement of `shape` with the node after it and the lines it cleared
+    fn get_placements(&self, shape: Shape, next_hold: Shape) -> Vec<(BlPlacement, Self, usize)> {
         let spawn = Piece::new(shape, Orientation::North)
-            .with(cc(4, 21))
+            .with(self.spawn)
             .to_bl_placement();
         if !spawn.is_in_free_space(&self.board) {
             return Vec::new();
@@ -123,7 +288,7 @@ impl Node {
 
         minimized_moves
             .into_iter()
-            .filter_map(|placement| {
+            .map(|placement| {
                 let mut new_node = self.clone();
                 let lines_cleared = placement
                     .place_on_and_clear_lines(&mut new_node.board)
@@ -155,12 +320,11 @@ impl Node {
                         atk += 10;
                     }
                     new_node.attack += atk;
-
-                    new_node.hold = next_hold;
-                    Some(new_node)
                 } else {
-                    None
+                    new_node.combo = 0;
                 }
+                new_node.hold = next_hold;
+                (placement, new_node, lines_cleared as usize)
             })
             .collect()
     }
@@ -184,7 +348,7 @@ fn dfs(node: Node, queue: &mut VecDeque<Shape>) -> (usize, usize) {
         let height = node.get_fall_height(*queue.front().unwrap_or(&node.hold));
         max_def = max_def.max(node.attack + height + 1);
     } else {
-        for child in children {
+        for (_, child) in children {
             let (atk, def) = dfs(child, queue);
             max_attack = max_attack.max(atk);
             max_def = max_def.max(def)
@@ -198,7 +362,7 @@ fn dfs(node: Node, queue: &mut VecDeque<Shape>) -> (usize, usize) {
             let height = node.get_fall_height(*queue.front().unwrap_or(&node.hold));
             max_def = max_def.max(node.attack + height + 1);
         } else {
-            for child in children {
+            for (_, child) in children {
                 let (atk, def) = dfs(child, queue);
                 max_attack = max_attack.max(atk);
                 max_def = max_def.max(def)
@@ -0,0 +1,194 @@
+use crate::confidence::Z_95;
+use crate::envelope::{read_versioned, EnvelopeError};
+use crate::player_stats::PlayerStats;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+///a numeric field that differs between two sets of stats, `field` is its path like `distributions.attack.mean`
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldDiff {
+    pub field: String,
+    pub before: Option<f64>,
+    pub after: Option<f64>,
+    pub delta: Option<f64>,
+    ///z score of the change, only for fields with a sample size and interval behind them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub z: Option<f64>,
+    ///the change is outside what the sample sizes would explain at 95% confidence
+    pub significant: bool,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct StatsDiff {
+    pub changes: Vec<FieldDiff>,
+    pub significant_changes: usize,
+}
+
+///reads stats written by `analyze`, with or without an envelope
+///
+///nulls, which is how NaN used to be written, read back as missing values rather than 0
+pub fn read_player_stats(json: &str) -> Result<PlayerStats, EnvelopeError> {
+    read_versioned(json)
+}
+
+///every number in the stats by its path, confidence intervals and histograms are left out and so are nulls
+///so a missing value never diffs as 0
+fn numbers(value: &Value, path: &str, out: &mut BTreeMap<String, f64>) {
+    let join = |key: &str| {
+        if path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", path, key)
+        }
+    };
+    match value {
+        Value::Number(number) => {
+            if let Some(number) = number.as_f64() {
+                out.insert(path.to_string(), number);
+            }
+        }
+        Value::Object(object) => {
+            for (key, value) in object {
+                if key != "confidence" && key != "histogram" {
+                    numbers(value, &join(key), out);
+                }
+            }
+        }
+        Value::Array(array) => {
+            for (i, value) in array.iter().enumerate() {
+                numbers(value, &join(&i.to_string()), out);
+            }
+        }
+        _ => {}
+    }
+}
+
+///standard error of a field, from its confidence interval or from the spread of its distribution
+fn standard_error(stats: &Value, field: &str) -> Option<f64> {
+    let ratio = &stats["confidence"][field];
+    if !ratio.is_null() {
+        let se = (ratio["upper"].as_f64()? - ratio["lower"].as_f64()?) / (2.0 * Z_95);
+        return se.is_finite().then_some(se);
+    }
+    let name = field
+        .strip_prefix("distributions.")?
+        .strip_suffix(".mean")?;
+    let distribution = &stats["distributions"][name];
+    let sd = distribution["sd"].as_f64()?;
+    let count = distribution["count"].as_f64()?;
+    (count > 1.0).then(|| sd / count.sqrt())
+}
+
+///changes from `before` to `after` in every numeric field
+///
+///fields backed by a ratio with a confidence interval or by a distribution get a z score and are flagged when
+///the change is significant at 95%, the rest only get their delta
+pub fn diff(before: &PlayerStats, after: &PlayerStats) -> StatsDiff {
+    let before = serde_json::to_value(before).unwrap(); //stats always serialize
+    let after = serde_json::to_value(after).unwrap(); //stats always serialize
+    let mut before_numbers = BTreeMap::new();
+    let mut after_numbers = BTreeMap::new();
+    numbers(&before, "", &mut before_numbers);
+    numbers(&after, "", &mut after_numbers);
+
+    let mut fields: Vec<_> = before_numbers.keys().collect();
+    fields.extend(after_numbers.keys());
+    fields.sort();
+    fields.dedup();
+
+    let changes: Vec<_> = fields
+        .into_iter()
+        .filter_map(|field| {
+            let (old, new) = (before_numbers.get(field), after_numbers.get(field));
+            if old == new {
+                return None;
+            }
+            let delta = old.zip(new).map(|(old, new)| new - old);
+            let z = delta.and_then(|delta| {
+                let se = standard_error(&before, field)?.hypot(standard_error(&after, field)?);
+                (se > 0.0).then(|| delta / se)
+            });
+            Some(FieldDiff {
+                field: field.clone(),
+                before: old.copied(),
+                after: new.copied(),
+                delta,
+                z,
+                significant: z.is_some_and(|z| z.abs() > Z_95),
+            })
+        })
+        .collect();
+
+    StatsDiff {
+        significant_changes: changes.iter().filter(|change| change.significant).count(),
+        changes,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::placement_stats::CumulativePlacementStats;
+
+    ///stats of 100 half second placements that sent `attack` between them
+    fn stats(attack: usize) -> PlayerStats {
+        PlayerStats::from(&CumulativePlacementStats {
+            delays: vec![30.0; 100],
+            attack,
+            ..Default::default()
+        })
+    }
+
+    fn apm_change(before: usize, after: usize) -> FieldDiff {
+        diff(&stats(before), &stats(after))
+            .changes
+            .into_iter()
+            .find(|change| change.field == "apm")
+            .unwrap()
+    }
+
+    #[test]
+    fn a_real_change_in_apm_is_significant() {
+        let change = apm_change(10, 100);
+        assert_eq!(change.before, Some(12.0));
+        assert_eq!(change.after, Some(120.0));
+        assert!(change.z.unwrap() > Z_95);
+        assert!(change.significant);
+    }
+
+    #[test]
+    fn a_change_within_the_noise_is_not_significant() {
+        let change = apm_change(10, 12);
+        assert!(change.z.is_some());
+        assert!(!change.significant);
+    }
+
+    #[test]
+    fn null_fields_are_missing_not_zero() {
+        let before = read_player_stats(r#"{"digSpeed": null, "kpp": 3.0, "apm": 50.0}"#).unwrap();
+        let after = read_player_stats(r#"{"digSpeed": 0.83, "kpp": null, "apm": 50.0}"#).unwrap();
+        let diff = diff(&before, &after);
+
+        let change = |field: &str| {
+            diff.changes
+                .iter()
+                .find(|change| change.field == field)
+                .unwrap()
+                .clone()
+        };
+        let dig_speed = change("digSpeed");
+        assert_eq!(dig_speed.before, None);
+        assert_eq!(dig_speed.after, Some(0.83));
+        assert_eq!(dig_speed.delta, None);
+        let kpp = change("kpp");
+        assert_eq!(kpp.before, Some(3.0));
+        assert_eq!(kpp.after, None);
+        assert_eq!(kpp.delta, None);
+        assert!(diff.changes.iter().all(|change| change.field != "apm"));
+        assert_eq!(diff.significant_changes, 0);
+    }
+}
@@ -0,0 +1,51 @@
+use crate::position::parse_queue;
+use crate::replay_response::{
+    Board, ClearType, MinoType, PlacementStats, DEFAULT_BOARD_HEIGHT, DEFAULT_BOARD_WIDTH,
+};
+
+///a t placed on an empty default board without clearing or sending anything, tests change what they need
+pub fn placement() -> PlacementStats {
+    PlacementStats {
+        shape: MinoType::T,
+        lines_cleared: 0,
+        garbage_cleared: 0,
+        keypresses: 0,
+        attack: Vec::new(),
+        clear_type: ClearType::None,
+        combo: 0,
+        btb_chain: 0,
+        btb_clear: false,
+        frame_delay: 10.0,
+        attack_received: Vec::new(),
+        attack_tanked: Vec::new(),
+        board: vec![MinoType::Empty; DEFAULT_BOARD_WIDTH * DEFAULT_BOARD_HEIGHT],
+        queue: Vec::new(),
+        board_width: DEFAULT_BOARD_WIDTH,
+        board_height: DEFAULT_BOARD_HEIGHT,
+    }
+}
+
+///an empty default height board `width` wide with `rows` at the bottom, written top first
+///
+///`#` is garbage, piece letters are that piece and anything else is empty
+pub fn board_of_width(width: usize, rows: &[&str]) -> Board {
+    let mut board = vec![MinoType::Empty; (DEFAULT_BOARD_HEIGHT - rows.len()) * width];
+    for row in rows {
+        assert_eq!(row.len(), width, "row {} isn't {} wide", row, width);
+        board.extend(row.chars().map(|cell| {
+            match cell {
+                '#' => MinoType::Garbage,
+                _ => parse_queue(&cell.to_string())
+                    .ok()
+                    .and_then(|piece| piece.first().copied())
+                    .unwrap_or(MinoType::Empty),
+            }
+        }));
+    }
+    board
+}
+
+///like `board_of_width` for a default width board
+pub fn board(rows: &[&str]) -> Board {
+    board_of_width(DEFAULT_BOARD_WIDTH, rows)
+}
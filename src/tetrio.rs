@@ -0,0 +1,511 @@
+use crate::attack::{attack_index, get_indexed_attack};
+use crate::engine::{Engine, Lock, Randomizer, RotationSystem};
+use crate::match_report::{Game, GameInput, GameMeta};
+use crate::replay_import::{GarbageRules, Recorder, ReplayError};
+use crate::replay_response::{MinoType, PlacementStats, DEFAULT_BOARD_HEIGHT, DEFAULT_BOARD_WIDTH};
+use serde_json::Value;
+
+///park-miller modulus used by the game's rng
+const RNG_MODULUS: u64 = 2147483647;
+///soft drop factor the game treats as instant
+const INSTANT_SOFT_DROP: f64 = 41.0;
+const DEFAULT_GRAVITY: f64 = 0.02;
+const DEFAULT_GARBAGE: GarbageRules = GarbageRules {
+    delay: 20.0,
+    cap: 8,
+};
+///perfect clears send this on top of the clear itself
+const PERFECT_CLEAR_ATTACK: usize = 10;
+
+///tetr.io's 7 bag, a park-miller rng shuffling the pieces in `MinoType` order
+pub struct TetrioRandomizer {
+    state: u64,
+}
+
+impl TetrioRandomizer {
+    pub fn new(seed: u64) -> Self {
+        let state = seed % RNG_MODULUS;
+        Self {
+            state: if state == 0 { RNG_MODULUS - 1 } else { state },
+        }
+    }
+
+    fn next_float(&mut self) -> f64 {
+        self.state = 16807 * self.state % RNG_MODULUS;
+        (self.state - 1) as f64 / (RNG_MODULUS - 1) as f64
+    }
+}
+
+impl Randomizer for TetrioRandomizer {
+    fn next_bag(&mut self) -> Vec<MinoType> {
+        let mut bag = vec![
+            MinoType::Z,
+            MinoType::L,
+            MinoType::O,
+            MinoType::S,
+            MinoType::I,
+            MinoType::J,
+            MinoType::T,
+        ];
+        for i in (1..bag.len()).rev() {
+            let j = (self.next_float() * (i + 1) as f64) as usize;
+            bag.swap(i, j);
+        }
+        bag
+    }
+}
+
+fn tetrio_attack(lock: &Lock) -> usize {
+    let Some(index) = attack_index(lock.clear_type) else {
+        return 0;
+    };
+    let btb = if lock.clear_type.is_btb_clear() {
+        lock.btb.unwrap_or(0)
+    } else {
+        0
+    };
+    let attack = get_indexed_attack(index, lock.combo.unwrap_or(0), btb);
+    if lock.perfect_clear {
+        attack + PERFECT_CLEAR_ATTACK
+    } else {
+        attack
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Handling {
+    das: f64,
+    arr: f64,
+    sdf: f64,
+    gravity: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct HeldKey {
+    direction: i32,
+    pressed: f64,
+    shifts: usize,
+}
+
+///keys held down and the time the engine has been advanced to
+struct Inputs {
+    handling: Handling,
+    ///held movement keys, the last one pressed is the one that moves the piece
+    held: Vec<HeldKey>,
+    soft_drop: bool,
+    gravity: f64,
+    now: f64,
+}
+
+impl Inputs {
+    fn charged(&self, key: &HeldKey, frame: f64) -> bool {
+        frame - key.pressed >= self.handling.das
+    }
+
+    ///runs das, arr, gravity and soft drop up to `frame`
+    fn advance<R: Randomizer>(&mut self, recorder: &mut Recorder<R>, frame: f64) {
+        let elapsed = (frame - self.now).max(0.0);
+        let handling = self.handling;
+        if let Some(key) = self.held.last().copied() {
+            if self.charged(&key, frame) {
+                let shifts = if handling.arr <= 0.0 {
+                    DEFAULT_BOARD_WIDTH
+                } else {
+                    ((frame - key.pressed - handling.das) / handling.arr) as usize + 1
+                };
+                if shifts > key.shifts {
+                    let distance = (shifts - key.shifts).min(DEFAULT_BOARD_WIDTH) as i32;
+                    recorder.engine().shift(key.direction * distance);
+                    self.held.last_mut().unwrap().shifts = shifts;
+                }
+            }
+        }
+        if self.soft_drop && handling.sdf >= INSTANT_SOFT_DROP {
+            recorder.engine().drop(DEFAULT_BOARD_HEIGHT);
+        } else {
+            let rate = if self.soft_drop {
+                handling.gravity * handling.sdf
+            } else {
+                handling.gravity
+            };
+            self.gravity += elapsed * rate;
+            let cells = self.gravity.floor();
+            recorder.engine().drop(cells as usize);
+            self.gravity -= cells;
+        }
+        self.now = frame;
+    }
+
+    ///a fresh piece gets moved by whatever is still held
+    fn spawned<R: Randomizer>(&mut self, recorder: &mut Recorder<R>) {
+        self.gravity = 0.0;
+        if let Some(key) = self.held.last().copied() {
+            if self.charged(&key, self.now) && self.handling.arr <= 0.0 {
+                recorder
+                    .engine()
+                    .shift(key.direction * DEFAULT_BOARD_WIDTH as i32);
+            }
+        }
+        if self.soft_drop && self.handling.sdf >= INSTANT_SOFT_DROP {
+            recorder.engine().drop(DEFAULT_BOARD_HEIGHT);
+        }
+    }
+}
+
+fn number(value: &Value, default: f64) -> f64 {
+    value.as_f64().unwrap_or(default)
+}
+
+///the garbage of an ige event with its `amt` and `column`, most replays nest the interaction one level deeper
+///
+///garbage shows up twice, as an `interaction` when it is sent and an `interaction_confirm` once it is queued on
+///the board, only the confirmation counts so every chunk is received once
+fn garbage_of(event: &Value) -> Option<&Value> {
+    let data = &event["data"];
+    [&data["data"], data]
+        .into_iter()
+        .find(|candidate| candidate["type"] == "interaction_confirm")
+        .map(|interaction| &interaction["data"])
+        .filter(|garbage| garbage["type"] == "garbage" && garbage["amt"].is_u64())
+}
+
+///simulates one player's event stream, returns their username and placements
+fn simulate(events: &[Value]) -> Result<(Option<String>, Vec<PlacementStats>), ReplayError> {
+    let options = events
+        .iter()
+        .find(|event| event["type"] == "full")
+        .map(|event| &event["data"]["options"])
+        .ok_or_else(|| ReplayError("no full event with the game options".to_string()))?;
+    let seed = options["seed"]
+        .as_u64()
+        .ok_or_else(|| ReplayError("game options have no seed".to_string()))?;
+    let handling = Handling {
+        das: number(&options["handling"]["das"], 10.0),
+        arr: number(&options["handling"]["arr"], 2.0),
+        sdf: number(&options["handling"]["sdf"], 6.0),
+        gravity: number(&options["g"], DEFAULT_GRAVITY),
+    };
+    let rules = GarbageRules {
+        delay: number(&options["garbagespeed"], DEFAULT_GARBAGE.delay),
+        cap: options["garbagecap"]
+            .as_u64()
+            .map_or(DEFAULT_GARBAGE.cap, |cap| cap as usize),
+    };
+
+    let engine = Engine::new(
+        DEFAULT_BOARD_WIDTH,
+        DEFAULT_BOARD_HEIGHT,
+        RotationSystem::SrsPlus,
+        TetrioRandomizer::new(seed),
+    );
+    let mut recorder = Recorder::new(engine, rules, tetrio_attack);
+    let mut inputs = Inputs {
+        handling,
+        held: Vec::new(),
+        soft_drop: false,
+        gravity: 0.0,
+        now: 0.0,
+    };
+
+    for event in events {
+        let frame = number(&event["frame"], inputs.now) + number(&event["data"]["subframe"], 0.0);
+        inputs.advance(&mut recorder, frame);
+        let key = event["data"]["key"].as_str().unwrap_or_default();
+        match event["type"].as_str().unwrap_or_default() {
+            "keydown" => {
+                //keys already held when the game started aren't presses
+                if event["data"]["hoisted"] != true {
+                    recorder.key_pressed();
+                }
+                match key {
+                    "moveLeft" | "moveRight" => {
+                        let direction = if key == "moveLeft" { -1 } else { 1 };
+                        inputs.held.retain(|held| held.direction != direction);
+                        inputs.held.push(HeldKey {
+                            direction,
+                            pressed: frame,
+                            shifts: 0,
+                        });
+                        recorder.engine().shift(direction);
+                    }
+                    "softDrop" => inputs.soft_drop = true,
+                    "rotateCW" => {
+                        recorder.engine().rotate(1);
+                    }
+                    "rotate180" => {
+                        recorder.engine().rotate(2);
+                    }
+                    "rotateCCW" => {
+                        recorder.engine().rotate(3);
+                    }
+                    "hold" => {
+                        recorder.engine().hold();
+                        inputs.spawned(&mut recorder);
+                    }
+                    "hardDrop" => {
+                        recorder.hard_drop(frame);
+                        inputs.spawned(&mut recorder);
+                    }
+                    _ => {}
+                }
+            }
+            "keyup" => match key {
+                "moveLeft" => inputs.held.retain(|held| held.direction != -1),
+                "moveRight" => inputs.held.retain(|held| held.direction != 1),
+                "softDrop" => inputs.soft_drop = false,
+                _ => {}
+            },
+            //targets only decide who gets sent garbage, what arrives shows up as ige events
+            "ige" => {
+                if let Some(garbage) = garbage_of(event) {
+                    recorder.receive(
+                        garbage["amt"].as_u64().unwrap_or(0) as usize,
+                        garbage["column"].as_u64().unwrap_or(0) as usize,
+                        frame,
+                    );
+                }
+            }
+            "end" => break,
+            _ => {}
+        }
+        if recorder.topped_out() {
+            break;
+        }
+    }
+
+    let username = options["username"].as_str().map(str::to_string);
+    Ok((username, recorder.finish()))
+}
+
+///reads a `.ttr` singleplayer replay or a `.ttrm` multiplayer replay into games ready to analyze
+///
+///multiplayer replays give one game per player per round with the round, opponent and result filled in
+pub fn import_tetrio_replay(json: &str) -> Result<Vec<GameInput>, ReplayError> {
+    let replay: Value =
+        serde_json::from_str(json).map_err(|error| ReplayError(error.to_string()))?;
+    let replay_id = replay["_id"].as_str().unwrap_or("replay");
+
+    let Some(rounds) = replay["data"].as_array() else {
+        let events = replay["data"]["events"]
+            .as_array()
+            .ok_or_else(|| ReplayError("no events in replay data".to_string()))?;
+        let (username, placements) = simulate(events)?;
+        return Ok(vec![GameInput::Game(Game {
+            meta: GameMeta {
+                game_id: format!("{}-{}", replay_id, username.unwrap_or_default()),
+                mode: replay["gametype"].as_str().map(str::to_string),
+                start_time: replay["ts"].as_str().map(str::to_string),
+                ..Default::default()
+            },
+            placements,
+        })]);
+    };
+
+    let mut games = Vec::new();
+    for (round, data) in rounds.iter().enumerate() {
+        let replays = data["replays"]
+            .as_array()
+            .ok_or_else(|| ReplayError(format!("round {} has no replays", round)))?;
+        let players = replays
+            .iter()
+            .map(|player| {
+                let events = player["events"]
+                    .as_array()
+                    .ok_or_else(|| ReplayError(format!("round {} has no events", round)))?;
+                simulate(events)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let results: Vec<_> = data["board"].as_array().cloned().unwrap_or_default();
+        let usernames: Vec<_> = players
+            .iter()
+            .map(|(username, _)| username.clone())
+            .collect();
+
+        for (i, (username, placements)) in players.into_iter().enumerate() {
+            let opponent = usernames
+                .iter()
+                .enumerate()
+                .find(|&(j, _)| j != i)
+                .and_then(|(_, opponent)| opponent.clone());
+            let won = results
+                .iter()
+                .find(|result| result["user"]["username"].as_str() == username.as_deref())
+                .and_then(|result| result["success"].as_bool());
+            games.push(GameInput::Game(Game {
+                meta: GameMeta {
+                    game_id: format!("{}-{}-{}", replay_id, round, username.unwrap_or_default()),
+                    match_id: Some(replay_id.to_string()),
+                    round: Some(round + 1),
+                    opponent_id: opponent,
+                    mode: replay["gametype"].as_str().map(str::to_string),
+                    won,
+                    start_time: replay["ts"].as_str().map(str::to_string),
+                },
+                placements,
+            }));
+        }
+    }
+    Ok(games)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay_response::ClearType;
+    use serde_json::json;
+
+    ///the bottom rows of a board top row first, pieces by their letter and garbage as `#`
+    fn bottom_rows(board: &[MinoType], rows: usize) -> Vec<String> {
+        let cells = board.len() - rows * DEFAULT_BOARD_WIDTH;
+        board[cells..]
+            .chunks(DEFAULT_BOARD_WIDTH)
+            .map(|row| {
+                row.iter()
+                    .map(|mino| match mino {
+                        MinoType::Empty => '.',
+                        MinoType::Garbage => '#',
+                        piece => format!("{:?}", piece).remove(0),
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn bags_follow_the_seed() {
+        let mut randomizer = TetrioRandomizer::new(1);
+        let bags: Vec<String> = (0..2)
+            .map(|_| {
+                randomizer
+                    .next_bag()
+                    .iter()
+                    .map(|mino| format!("{:?}", mino))
+                    .collect()
+            })
+            .collect();
+        assert_eq!(bags, ["OJILSTZ", "TOLJSIZ"]);
+
+        let mut randomizer = TetrioRandomizer::new(12345);
+        let bag: String = randomizer
+            .next_bag()
+            .iter()
+            .map(|mino| format!("{:?}", mino))
+            .collect();
+        assert_eq!(bag, "LOSTIJZ");
+    }
+
+    #[test]
+    fn replay_places_pieces_and_garbage() {
+        //seed 1 starts with o, j and i, the o tanks two lines of garbage and the i clears them
+        let key = |frame: usize, kind: &str, key: &str| {
+            let data = json!({ "key": key });
+            json!({ "frame": frame, "type": kind, "data": data })
+        };
+        //the same chunk of garbage as it is sent and as it is confirmed, laid out like a .ttrm
+        let interaction = |kind: &str| {
+            json!({
+                "frame": 0,
+                "type": "ige",
+                "data": {
+                    "id": 1,
+                    "frame": 0,
+                    "type": "ige",
+                    "data": {
+                        "type": kind,
+                        "data": {
+                            "type": "garbage",
+                            "iid": 1,
+                            "ackiid": 0,
+                            "amt": 2,
+                            "x": 4,
+                            "y": 0,
+                            "column": 5,
+                            "username": "opponent"
+                        },
+                        "sender": "opponent",
+                        "sent_frame": 0,
+                        "cid": 1
+                    }
+                }
+            })
+        };
+        let mut events = vec![
+            json!({
+                "frame": 0,
+                "type": "full",
+                "data": { "options": { "seed": 1, "username": "player" } }
+            }),
+            interaction("interaction"),
+            interaction("interaction_confirm"),
+        ];
+        for frame in [1, 3, 5, 7] {
+            events.push(key(frame, "keydown", "moveLeft"));
+            events.push(key(frame + 1, "keyup", "moveLeft"));
+        }
+        events.push(key(30, "keydown", "hardDrop"));
+        for frame in [31, 33, 35] {
+            events.push(key(frame, "keydown", "moveRight"));
+            events.push(key(frame + 1, "keyup", "moveRight"));
+        }
+        events.push(key(40, "keydown", "hardDrop"));
+        events.push(key(41, "keydown", "rotateCW"));
+        events.push(key(42, "keydown", "hardDrop"));
+        let replay = json!({ "_id": "replay", "data": { "events": events } });
+
+        let games = import_tetrio_replay(&replay.to_string()).unwrap();
+        let [GameInput::Game(game)] = &games[..] else {
+            panic!("a singleplayer replay is a single game");
+        };
+        assert_eq!(game.meta.game_id, "replay-player");
+        let placements = &game.placements;
+        assert_eq!(placements.len(), 3);
+
+        assert_eq!(placements[0].shape, MinoType::O);
+        assert_eq!(placements[0].keypresses, 5);
+        assert_eq!(placements[0].attack_received, [2]);
+        assert_eq!(placements[0].attack_tanked, [2]);
+        assert!(placements[0].attack.is_empty());
+        assert_eq!(
+            bottom_rows(&placements[0].board, 4),
+            ["OO........", "OO........", "#####.####", "#####.####"]
+        );
+
+        assert_eq!(placements[1].shape, MinoType::J);
+        assert!(placements[1].attack_tanked.is_empty());
+        assert!(placements[1].attack.is_empty());
+        assert_eq!(
+            bottom_rows(&placements[1].board, 4),
+            ["OO....J...", "OO....JJJ.", "#####.####", "#####.####"]
+        );
+
+        assert_eq!(placements[2].shape, MinoType::I);
+        assert_eq!(placements[2].lines_cleared, 2);
+        assert_eq!(placements[2].garbage_cleared, 2);
+        assert_eq!(placements[2].attack, [1]);
+        assert_eq!(
+            bottom_rows(&placements[2].board, 3),
+            ["..........", "OO...IJ...", "OO...IJJJ."]
+        );
+    }
+
+    #[test]
+    fn perfect_clears_add_their_bonus() {
+        let lock = Lock {
+            shape: MinoType::I,
+            lines_cleared: 4,
+            garbage_cleared: 0,
+            clear_type: ClearType::Quad,
+            perfect_clear: true,
+            btb_clear: false,
+            combo: Some(0),
+            btb: Some(0),
+        };
+        assert_eq!(tetrio_attack(&lock), 4 + PERFECT_CLEAR_ATTACK);
+    }
+
+    #[test]
+    fn input_that_isnt_a_replay_is_an_error() {
+        assert!(import_tetrio_replay("not a replay").is_err());
+        assert!(import_tetrio_replay("{\"data\": {}}").is_err());
+    }
+}
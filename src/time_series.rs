@@ -0,0 +1,150 @@
+use crate::placement_stats::CumulativePlacementStats;
+use serde::{Deserialize, Serialize};
+
+///how far back the rolling window ending at every placement reaches
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Window {
+    Placements(usize),
+    Seconds(f64),
+}
+
+///stats over the window ending at one placement, windows near the start of a game are cut short by it
+#[derive(Serialize, Default, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TimeSeriesPoint {
+    ///index of the first placement in the window
+    pub placement: usize,
+    pub placements: usize,
+    ///seconds into the game at the end of the window
+    pub time: f64,
+    ///none for a window that took no time
+    pub apm: Option<f64>,
+    pub pps: Option<f64>,
+    pub stack_height: f64,
+    pub garbage_height: f64,
+    pub attack: usize,
+    ///none when the solver didn't look at any board in the window
+    pub attack_potential: Option<f64>,
+}
+
+#[derive(Serialize, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GameTimeSeries {
+    pub game_id: Option<String>,
+    pub points: Vec<TimeSeriesPoint>,
+}
+
+///a point for every placement of a single game, over the window that ends at it
+///
+///the stats must not be absorbed from several games
+pub fn time_series(stats: &CumulativePlacementStats, window: Window) -> Vec<TimeSeriesPoint> {
+    //frames into the game when every placement locked
+    let ends: Vec<f64> = stats
+        .delays
+        .iter()
+        .scan(0.0, |frames, delay| {
+            *frames += delay;
+            Some(*frames)
+        })
+        .collect();
+    let mut start = 0;
+    (0..ends.len())
+        .map(|i| {
+            let start_frames = match window {
+                Window::Placements(count) => {
+                    start = (i + 1).saturating_sub(count.max(1));
+                    start.checked_sub(1).map_or(0.0, |before| ends[before])
+                }
+                Window::Seconds(secs) => {
+                    let from = (ends[i] - secs * 60.0).max(0.0);
+                    //a placement that locked right as the window opens came before it
+                    while start < i && ends[start] <= from {
+                        start += 1;
+                    }
+                    from
+                }
+            };
+            point(stats, start..i + 1, start_frames, ends[i])
+        })
+        .collect()
+}
+
+fn point(
+    stats: &CumulativePlacementStats,
+    range: std::ops::Range<usize>,
+    start_frames: f64,
+    end_frames: f64,
+) -> TimeSeriesPoint {
+    let placements = range.len() as f64;
+    let secs = (end_frames - start_frames) / 60.0;
+    let attack = stats.attacks[range.clone()].iter().sum::<usize>();
+    let potentials: Vec<_> = stats.attack_potentials[range.clone()]
+        .iter()
+        .flatten()
+        .collect();
+    TimeSeriesPoint {
+        placement: range.start,
+        placements: range.len(),
+        time: end_frames / 60.0,
+        apm: (secs > 0.0).then(|| attack as f64 * 60.0 / secs),
+        pps: (secs > 0.0).then(|| placements / secs),
+        stack_height: stats.stack_heights[range.clone()].iter().sum::<usize>() as f64 / placements,
+        garbage_height: stats.garbage_heights[range].iter().sum::<usize>() as f64 / placements,
+        attack,
+        attack_potential: (!potentials.is_empty())
+            .then(|| potentials.iter().copied().sum::<usize>() as f64 / potentials.len() as f64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    ///a game of placements that each took `delays` frames and sent `attacks`
+    fn stats(delays: &[f64], attacks: &[usize]) -> CumulativePlacementStats {
+        CumulativePlacementStats {
+            delays: delays.to_vec(),
+            attacks: attacks.to_vec(),
+            attack_potentials: vec![None; delays.len()],
+            stack_heights: vec![0; delays.len()],
+            garbage_heights: vec![0; delays.len()],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn placement_windows_slide_by_one() {
+        let stats = stats(&[60.0, 60.0, 60.0], &[0, 2, 4]);
+        let points = time_series(&stats, Window::Placements(2));
+        let windows: Vec<_> = points
+            .iter()
+            .map(|point| (point.placement, point.placements, point.attack))
+            .collect();
+        assert_eq!(windows, [(0, 1, 0), (0, 2, 2), (1, 2, 6)]);
+        assert_eq!(points[2].pps, Some(1.0));
+        assert_eq!(points[2].apm, Some(180.0));
+        assert_eq!(points[2].attack_potential, None);
+    }
+
+    #[test]
+    fn second_windows_slide_by_one() {
+        let stats = stats(&[30.0, 30.0, 60.0, 120.0], &[1, 1, 1, 1]);
+        let windows: Vec<_> = time_series(&stats, Window::Seconds(1.0))
+            .iter()
+            .map(|point| (point.placement, point.placements))
+            .collect();
+        assert_eq!(windows, [(0, 1), (0, 2), (2, 1), (3, 1)]);
+    }
+
+    #[test]
+    fn windows_without_time_have_no_rates() {
+        let stats = stats(&[0.0, 60.0], &[3, 0]);
+        let points = time_series(&stats, Window::Seconds(0.0));
+        assert_eq!(points[0].apm, None);
+        assert_eq!(points[0].pps, None);
+        let points = time_series(&stats, Window::Placements(1));
+        assert_eq!(points[0].apm, None);
+        assert_eq!(points[1].pps, Some(1.0));
+    }
+}
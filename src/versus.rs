@@ -0,0 +1,388 @@
+use crate::bitboard::BitBoard;
+use crate::board_analyzer::get_garbage_height;
+use crate::garbage_ledger::GarbageLedger;
+use crate::match_report::GameInput;
+use crate::placement_stats::round_delay;
+use crate::replay_response::PlacementStats;
+use serde::Serialize;
+use std::collections::VecDeque;
+
+///attack a single combo has to send to count as a spike
+const SPIKE_ATTACK: usize = 9;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Player,
+    Opponent,
+}
+
+///what the winner of a round did better than the loser
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Factor {
+    Spikes,
+    Cancels,
+    Tanks,
+}
+
+///a side's state right after one of its placements
+#[derive(Debug, Clone)]
+struct Snapshot {
+    frame: f64,
+    ///garbage waiting to rise plus garbage already on the board
+    pressure: usize,
+    attack: usize,
+    ///attack left over after cancelling the side's own garbage, what goes out to the other side
+    sent: usize,
+    received: Vec<usize>,
+    cancelled: usize,
+    tanked: usize,
+}
+
+///attack that left one side and hasn't shown up on the other yet
+#[derive(Debug, Clone, Copy)]
+struct InFlight {
+    frame: f64,
+    lines: usize,
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct Spike {
+    pub side: Side,
+    pub frame: f64,
+    pub attack: usize,
+}
+
+///lines sent by one side that arrived on the other
+#[derive(Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct GarbageExchange {
+    pub from: Side,
+    pub sent_frame: f64,
+    pub received_frame: f64,
+    pub lines: usize,
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct MomentumPoint {
+    pub frame: f64,
+    ///garbage waiting, on the board, or sent by the other side and still on its way
+    pub player_pressure: usize,
+    pub opponent_pressure: usize,
+    ///positive while the opponent is under more pressure than the player
+    pub momentum: i64,
+}
+
+///the moment one side took over the lead
+#[derive(Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct MomentumSwing {
+    pub frame: f64,
+    pub leader: Side,
+    pub momentum: i64,
+}
+
+#[derive(Serialize, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SideSummary {
+    pub attack: usize,
+    ///attack left after cancelling the side's own garbage
+    pub sent: usize,
+    ///sent lines that showed up as received on the other side
+    pub landed: usize,
+    pub spikes: usize,
+    pub spike_attack: usize,
+    pub cancelled: usize,
+    pub tanked: usize,
+    ///frames spent under more pressure than the other side
+    pub frames_under_pressure: f64,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct VersusReport {
+    pub winner: Side,
+    pub frames: f64,
+    ///the factor the winner did best in, none when they weren't ahead in any or were ahead by as much in two
+    pub deciding_factor: Option<Factor>,
+    pub player: SideSummary,
+    pub opponent: SideSummary,
+    pub spikes: Vec<Spike>,
+    pub exchanges: Vec<GarbageExchange>,
+    pub timeline: Vec<MomentumPoint>,
+    pub swings: Vec<MomentumSwing>,
+}
+
+fn snapshots(game: &[PlacementStats]) -> Vec<Snapshot> {
+    let mut ledger = GarbageLedger::default();
+    let mut frame = 0.0;
+    game.iter()
+        .enumerate()
+        .map(|(i, placement)| {
+            frame += round_delay(placement.frame_delay);
+            let pending = ledger.pending() + placement.attack_received.iter().sum::<usize>();
+            ledger.record(i, placement);
+            let tanked = placement.attack_tanked.iter().sum::<usize>();
+            let board = BitBoard::new(&placement.board, placement.board_width);
+            let attack = placement.attack.iter().sum();
+            let cancelled = pending.saturating_sub(ledger.pending() + tanked);
+            Snapshot {
+                frame,
+                pressure: ledger.pending() + get_garbage_height(&board),
+                attack,
+                sent: attack.saturating_sub(cancelled),
+                received: placement.attack_received.clone(),
+                cancelled,
+                tanked,
+            }
+        })
+        .collect()
+}
+
+fn summarize(
+    side: Side,
+    game: &[PlacementStats],
+    snapshots: &[Snapshot],
+) -> (SideSummary, Vec<Spike>) {
+    let mut summary = SideSummary::default();
+    let mut spikes = Vec::new();
+    let mut combo_attack = 0;
+    for (placement, snapshot) in game.iter().zip(snapshots) {
+        summary.attack += snapshot.attack;
+        summary.sent += snapshot.sent;
+        summary.cancelled += snapshot.cancelled;
+        summary.tanked += snapshot.tanked;
+        if placement.lines_cleared > 0 {
+            combo_attack += snapshot.attack;
+            continue;
+        }
+        if combo_attack >= SPIKE_ATTACK {
+            spikes.push(Spike {
+                side,
+                frame: snapshot.frame,
+                attack: combo_attack,
+            });
+        }
+        combo_attack = 0;
+    }
+    if combo_attack >= SPIKE_ATTACK {
+        if let Some(last) = snapshots.last() {
+            spikes.push(Spike {
+                side,
+                frame: last.frame,
+                attack: combo_attack,
+            });
+        }
+    }
+    summary.spikes = spikes.len();
+    summary.spike_attack = spikes.iter().map(|spike| spike.attack).sum();
+    (summary, spikes)
+}
+
+///lines both players' placements up on their shared clock and follows the garbage sent between them
+///
+///what a side receives is matched to what the other side sent in the order it was sent, received lines with
+///nothing sent before them came from somewhere else and aren't matched
+pub fn analyze_versus(player: &GameInput, opponent: &GameInput) -> VersusReport {
+    let sides = [Side::Player, Side::Opponent];
+    let games = [player.placements(), opponent.placements()];
+    let snapshots = games.map(snapshots);
+    let (player_summary, mut spikes) = summarize(Side::Player, games[0], &snapshots[0]);
+    let (opponent_summary, opponent_spikes) = summarize(Side::Opponent, games[1], &snapshots[1]);
+    spikes.extend(opponent_spikes);
+    spikes.sort_by(|a, b| a.frame.total_cmp(&b.frame));
+    let mut summaries = [player_summary, opponent_summary];
+
+    let mut timeline: Vec<MomentumPoint> = Vec::new();
+    let mut swings = Vec::new();
+    let mut exchanges = Vec::new();
+    let mut leader = None;
+    let mut next = [0, 0];
+    let mut in_flight: [VecDeque<InFlight>; 2] = Default::default();
+    let mut own_pressure = [0, 0];
+    //the side that places next, the player first when both place on the same frame
+    while let Some(side) = (0..2)
+        .filter(|&side| next[side] < snapshots[side].len())
+        .min_by(|&a, &b| {
+            let frame = |side: usize| snapshots[side][next[side]].frame;
+            frame(a).total_cmp(&frame(b))
+        })
+    {
+        let snapshot = &snapshots[side][next[side]];
+        next[side] += 1;
+        let other = 1 - side;
+        let frame = snapshot.frame;
+
+        for &received in &snapshot.received {
+            let mut lines = received;
+            while lines > 0 {
+                let Some(sent) = in_flight[other].front_mut() else {
+                    break;
+                };
+                let arrived = lines.min(sent.lines);
+                exchanges.push(GarbageExchange {
+                    from: sides[other],
+                    sent_frame: sent.frame,
+                    received_frame: frame,
+                    lines: arrived,
+                });
+                summaries[other].landed += arrived;
+                sent.lines -= arrived;
+                lines -= arrived;
+                if sent.lines == 0 {
+                    in_flight[other].pop_front();
+                }
+            }
+        }
+
+        //attack cancels what the other side sent before it goes out, even if it hasn't shown up yet
+        let mut sent = snapshot.sent;
+        while sent > 0 {
+            let Some(incoming) = in_flight[other].front_mut() else {
+                break;
+            };
+            let cancelled = sent.min(incoming.lines);
+            summaries[side].cancelled += cancelled;
+            incoming.lines -= cancelled;
+            sent -= cancelled;
+            if incoming.lines == 0 {
+                in_flight[other].pop_front();
+            }
+        }
+        if sent > 0 {
+            in_flight[side].push_back(InFlight { frame, lines: sent });
+        }
+        own_pressure[side] = snapshot.pressure;
+        let pressure = |side: usize| {
+            own_pressure[side]
+                + in_flight[1 - side]
+                    .iter()
+                    .map(|sent| sent.lines)
+                    .sum::<usize>()
+        };
+        let (player_pressure, opponent_pressure) = (pressure(0), pressure(1));
+
+        //whoever was under more pressure since the last point carries it until now
+        if let Some(last) = timeline.last() {
+            let elapsed = frame - last.frame;
+            if last.momentum > 0 {
+                summaries[1].frames_under_pressure += elapsed;
+            } else if last.momentum < 0 {
+                summaries[0].frames_under_pressure += elapsed;
+            }
+        }
+
+        let momentum = opponent_pressure as i64 - player_pressure as i64;
+        let current = match momentum {
+            m if m > 0 => Some(Side::Player),
+            m if m < 0 => Some(Side::Opponent),
+            _ => leader,
+        };
+        if let Some(side) = current.filter(|&side| Some(side) != leader) {
+            swings.push(MomentumSwing {
+                frame,
+                leader: side,
+                momentum,
+            });
+        }
+        leader = current;
+        timeline.push(MomentumPoint {
+            frame,
+            player_pressure,
+            opponent_pressure,
+            momentum,
+        });
+    }
+    let [player_summary, opponent_summary] = summaries;
+
+    let [player_frames, opponent_frames] =
+        snapshots.map(|snapshots| snapshots.last().map_or(0.0, |s| s.frame));
+    //without a recorded result the side that kept placing longer survived
+    let winner = match player.meta().and_then(|meta| meta.won) {
+        Some(true) => Side::Player,
+        Some(false) => Side::Opponent,
+        None if player_frames >= opponent_frames => Side::Player,
+        None => Side::Opponent,
+    };
+    let (winning, losing) = match winner {
+        Side::Player => (&player_summary, &opponent_summary),
+        Side::Opponent => (&opponent_summary, &player_summary),
+    };
+    let margins = [
+        (
+            Factor::Spikes,
+            winning.spike_attack as i64 - losing.spike_attack as i64,
+        ),
+        (
+            Factor::Cancels,
+            winning.cancelled as i64 - losing.cancelled as i64,
+        ),
+        (Factor::Tanks, losing.tanked as i64 - winning.tanked as i64),
+    ];
+    let best = margins.iter().map(|&(_, margin)| margin).max().unwrap_or(0);
+    let mut leading = margins.iter().filter(|&&(_, margin)| margin == best);
+    let deciding_factor = match (leading.next(), leading.next()) {
+        (Some(&(factor, _)), None) if best > 0 => Some(factor),
+        _ => None,
+    };
+
+    VersusReport {
+        winner,
+        frames: player_frames.max(opponent_frames),
+        deciding_factor,
+        player: player_summary,
+        opponent: opponent_summary,
+        spikes,
+        exchanges,
+        timeline,
+        swings,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    fn placement(delay: f64, attack: &[usize], received: &[usize]) -> PlacementStats {
+        PlacementStats {
+            frame_delay: delay,
+            attack: attack.to_vec(),
+            attack_received: received.to_vec(),
+            ..test_support::placement()
+        }
+    }
+
+    #[test]
+    fn received_garbage_is_matched_to_what_the_other_side_sent() {
+        let player = GameInput::Placements(vec![placement(60.0, &[4], &[])]);
+        let opponent =
+            GameInput::Placements(vec![placement(30.0, &[], &[]), placement(90.0, &[], &[4])]);
+        let report = analyze_versus(&player, &opponent);
+
+        let [exchange] = report.exchanges[..] else {
+            panic!("the player's attack arrives once");
+        };
+        assert_eq!(exchange.from, Side::Player);
+        assert_eq!(exchange.sent_frame, 60.0);
+        assert_eq!(exchange.received_frame, 120.0);
+        assert_eq!(exchange.lines, 4);
+        assert_eq!(report.player.sent, 4);
+        assert_eq!(report.player.landed, 4);
+
+        //the attack puts the opponent under pressure from the moment it is sent
+        let pressure: Vec<_> = report
+            .timeline
+            .iter()
+            .map(|point| (point.frame, point.opponent_pressure))
+            .collect();
+        assert_eq!(pressure, [(30.0, 0), (60.0, 4), (120.0, 4)]);
+
+        //the opponent outlasted the player without doing better at anything
+        assert_eq!(report.winner, Side::Opponent);
+        assert_eq!(report.deciding_factor, None);
+    }
+}